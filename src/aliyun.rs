@@ -1,9 +1,12 @@
-use chrono::Utc;
-use reqwest::Version;
-use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, DATE, HeaderMap, HeaderValue, HOST};
+use reqwest::{StatusCode, Version};
+use reqwest::header::{AUTHORIZATION, HeaderValue};
 use serde::{Serialize, Deserialize};
-use crate::{Context, HeaderName, TransError, TransRes, TransResult};
-use crate::common::{base64_byte, md5_str, sha1_hmac};
+use serde::de::DeserializeOwned;
+use crate::{Context, TransError, TransReq, TransRes, TransResult};
+use crate::common::{base64_byte, classify_error, json_text, md5_str, read_json, retry_on_skew, send_error, server_date, with_account};
+use crate::sign::{Acs3Signer, AcsSigner, CanonicalRequest};
+
+const ALIYUN_BATCH_SIZE: usize = 50;
 
 #[derive(Serialize, Debug)]
 pub struct AliYunReq {
@@ -24,15 +27,25 @@ pub struct AliYunReq {
 }
 
 impl AliYunReq {
-  fn create(src: &str, source: &str, target: &str) -> Self {
+  fn create(src: &str, source: &str, target: &str, scene: &str, format: &str) -> Self {
     return AliYunReq {
-      format: String::from("text"),
+      format: String::from(format),
       source: String::from(source),
       target: String::from(target),
       src: String::from(src),
-      scene: String::from("general")
+      scene: String::from(scene)
     }
   }
+
+  fn to_query(&self) -> [(&str, &str); 5] {
+    return [
+      ("FormatType", self.format.as_str()),
+      ("SourceLanguage", self.source.as_str()),
+      ("TargetLanguage", self.target.as_str()),
+      ("SourceText", self.src.as_str()),
+      ("Scene", self.scene.as_str())
+    ];
+  }
 }
 
 #[derive(Deserialize, Debug)]
@@ -62,39 +75,155 @@ struct AliYunInnerRes {
   dst: String,
 }
 
-pub async fn trans(context: &Context) -> TransResult<TransRes> {
+#[derive(Deserialize, Debug)]
+struct AliYunBatchRes {
+  #[serde(rename(deserialize = "TranslatedList"))]
+  list: Vec<AliYunBatchItem>,
+}
+
+impl AliYunBatchRes {
+  fn to_trans_list(&self, len: usize) -> TransResult<Vec<TransRes>> {
+    let mut results = vec![None; len];
+    for item in self.list.iter() {
+      let index = json_text(&item.index).parse::<usize>().map_err(|_| TransError::ResponseError)?;
+      let slot = results.get_mut(index).ok_or(TransError::ResponseError)?;
+      *slot = item.dst.clone();
+    }
+    return results.into_iter()
+      .map(|dst| dst.map(TransRes::create).ok_or(TransError::ResponseError))
+      .collect();
+  }
+}
+
+#[derive(Deserialize, Debug)]
+struct AliYunBatchItem {
+  index: serde_json::Value,
+
+  #[serde(rename(deserialize = "translated"))]
+  dst: Option<String>,
+}
+
+// `{"Code": "InvalidTimeStamp.Expired", "Message": ".."}` for both APIs
+fn error(status: StatusCode, body: &serde_json::Value) -> TransError {
+  return classify_error(status, json_text(&body["Code"]).as_str(), json_text(&body["Message"]).as_str());
+}
+
+pub async fn trans(context: &Context, req: &TransReq) -> TransResult<TransRes> {
+  return with_account(context, "aliyun", context.aliyun_accounts(), |account| {
+    retry_on_skew(context, move || trans_once(context, req, account))
+  }).await;
+}
+
+async fn trans_once(context: &Context, req: &TransReq, account: usize) -> TransResult<TransRes> {
+  return match context.aliyun_api() {
+    "rpc" => trans_rpc(context, req, account).await,
+    "legacy" => trans_legacy(context, req, account).await,
+    _ => Err(TransError::AliYunConfig),
+  };
+}
+
+// TranslateGeneral/TranslateECommerce RPC actions signed with ACS3-HMAC-SHA256
+async fn trans_rpc(context: &Context, req: &TransReq, account: usize) -> TransResult<TransRes> {
+  let scene = context.aliyun_scene().ok_or(TransError::AliYunConfig)?;
+  let body = AliYunReq::create(req.src_ref(), req.source_ref(), req.target_ref(), scene, context.aliyun_format());
+  let response = call_rpc::<AliYunRes>(context, account, context.aliyun_action(), &body.to_query()).await?;
+  return Ok(response.to_trans_res());
+}
+
+// `GetBatchTranslate`, only through the RPC API
+pub async fn trans_batch(context: &Context, segments: &[String]) -> TransResult<Vec<TransRes>> {
+  if context.aliyun_api() != "rpc" {
+    return Err(TransError::AliYunConfig);
+  }
   let req = context.req_ref();
-  let client = context.client_ref();
+  let scene = context.aliyun_scene().ok_or(TransError::AliYunConfig)?;
+  let api_type = match context.aliyun_action() {
+    "TranslateECommerce" => "translate_ecommerce",
+    _ => "translate_standard",
+  };
+  let mut results = Vec::with_capacity(segments.len());
+  for chunk in segments.chunks(ALIYUN_BATCH_SIZE) {
+    // `{"0": "..", "1": ".."}`, translations come back keyed by the same index
+    let texts: serde_json::Map<String, serde_json::Value> = chunk.iter().enumerate()
+      .map(|(index, text)| (index.to_string(), serde_json::Value::String(text.clone())))
+      .collect();
+    let texts = serde_json::Value::Object(texts).to_string();
+    let query = [
+      ("FormatType", context.aliyun_format()),
+      ("SourceLanguage", req.source_ref()),
+      ("TargetLanguage", req.target_ref()),
+      ("SourceText", texts.as_str()),
+      ("Scene", scene),
+      ("ApiType", api_type),
+    ];
+    let query = &query;
+    let response = with_account(context, "aliyun", context.aliyun_accounts(), |account| {
+      retry_on_skew(context, move || call_rpc::<AliYunBatchRes>(context, account, "GetBatchTranslate", query))
+    }).await?;
+    results.extend(response.to_trans_list(chunk.len())?);
+  }
+  return Ok(results);
+}
+
+async fn call_rpc<R: DeserializeOwned>(context: &Context, account: usize, action: &str, query: &[(&str, &str)]) -> TransResult<R> {
+  let client = context.client_ref("aliyun");
+  let current_time = context.now();
+  let nonce = context.nonce();
+
+  let mut sign_request = CanonicalRequest::create("POST", "/", "");
+  for (name, value) in query {
+    sign_request = sign_request.query(name, value);
+  }
+  let content_hash = sign_request.payload_hash();
+  let mut sign_request = sign_request
+    .header("host", "mt.cn-hangzhou.aliyuncs.com")
+    .header("x-acs-action", action)
+    .header("x-acs-version", "2018-10-12")
+    .header("x-acs-date", Acs3Signer::timestamp(&current_time).as_str())
+    .header("x-acs-signature-nonce", nonce.as_str())
+    .header("x-acs-content-sha256", content_hash.as_str());
+  if let Some(token) = context.aliyun_token(account) {
+    sign_request = sign_request.header("x-acs-security-token", token);
+  }
+  let authorization = Acs3Signer::sign(context.aliyun_key(account)?, context.aliyun_secret(account)?, &sign_request)?;
+  let mut headers = sign_request.header_map().map_err(|_| TransError::AliYunConfig)?;
+  headers.insert(AUTHORIZATION, HeaderValue::from_str(authorization.as_str()).unwrap());
 
-  let current_time = Utc::now();
-  let current_time_str: String = current_time.to_rfc2822();
-  let nonce = current_time.timestamp().to_string();
+  // the query is sent exactly as it was signed
+  let address = format!("{}/?{}", context.aliyun_rpc_address(), sign_request.canonical_query());
+  let mut request_builder = client.post(address);
+  request_builder = request_builder.version(Version::HTTP_11);
+  request_builder = request_builder.headers(headers);
+  let response = request_builder.send()
+    .await.map_err(send_error)?;
+  let date = server_date(&response);
+  return read_json::<R>(response, error)
+    .await.map_err(|error| context.correct_clock(error, date));
+}
+
+// ROA endpoint `/api/translate/web/general` signed with HMAC-SHA1
+async fn trans_legacy(context: &Context, req: &TransReq, account: usize) -> TransResult<TransRes> {
+  let client = context.client_ref("aliyun");
 
-  let body = AliYunReq::create(req.src_ref(), req.source_ref(), req.target_ref());
+  let current_time = context.now();
+  let nonce = context.nonce();
+
+  let body = AliYunReq::create(req.src_ref(), req.source_ref(), req.target_ref(), "general", context.aliyun_format());
   let body_str = serde_json::to_string(&body).unwrap();
   let body_hash = base64_byte(md5_str(body_str.as_str()).as_slice());
-  let mut sign_request = Vec::with_capacity(8);
-  sign_request.push("POST");
-  sign_request.push("application/json");
-  sign_request.push(body_hash.as_str());
-  sign_request.push("application/json; charset=utf-8");
-  sign_request.push(current_time_str.as_str());
-  sign_request.push("x-acs-signature-method:HMAC-SHA1");
-  let signature_nonce = format!("x-acs-signature-nonce:{}", nonce);
-  sign_request.push(signature_nonce.as_str());
-  sign_request.push("/api/translate/web/general");
-  let sign_request_str = sign_request.join("\n");
-
-  let signature = base64_byte(sha1_hmac(context.aliyun_secret()?.as_bytes(), sign_request_str.as_bytes())?.as_slice());
-  let authorization = format!("acs {}:{}", context.aliyun_key()?, signature);
-  let mut headers = HeaderMap::with_capacity(5);
-  headers.insert(HeaderName::from_static("content-md5"), HeaderValue::from_str(body_hash.as_str()).unwrap());
-  headers.insert(HeaderName::from_static("x-acs-signature-nonce"), HeaderValue::from_str(nonce.as_str()).unwrap());
-  headers.insert(HeaderName::from_static("x-acs-signature-method"), HeaderValue::from_str("HMAC-SHA1").unwrap());
-  headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json; charset=utf-8").unwrap());
-  headers.insert(ACCEPT, HeaderValue::from_str("application/json").unwrap());
-  headers.insert(HOST, HeaderValue::from_str("mt.cn-hangzhou.aliyuncs.com").unwrap());
-  headers.insert(DATE, HeaderValue::from_str(current_time_str.as_str()).unwrap());
+  let mut sign_request = CanonicalRequest::create("POST", "/api/translate/web/general", body_str.as_str())
+    .header("accept", "application/json")
+    .header("content-md5", body_hash.as_str())
+    .header("content-type", "application/json; charset=utf-8")
+    .header("date", AcsSigner::timestamp(&current_time).as_str())
+    .header("host", "mt.cn-hangzhou.aliyuncs.com")
+    .header("x-acs-signature-method", "HMAC-SHA1")
+    .header("x-acs-signature-nonce", nonce.as_str());
+  if let Some(token) = context.aliyun_token(account) {
+    sign_request = sign_request.header("x-acs-security-token", token);
+  }
+  let authorization = AcsSigner::sign(context.aliyun_key(account)?, context.aliyun_secret(account)?, &sign_request)?;
+  let mut headers = sign_request.header_map().map_err(|_| TransError::AliYunConfig)?;
   headers.insert(AUTHORIZATION, HeaderValue::from_str(authorization.as_str()).unwrap());
 
   let mut request_builder = client.post(context.aliyun_address());
@@ -102,9 +231,10 @@ pub async fn trans(context: &Context) -> TransResult<TransRes> {
   request_builder = request_builder.headers(headers);
   request_builder = request_builder.body(body_str);
   let response = request_builder.send()
-    .await.map_err(|_| TransError::RequestError)?
-    .json::<AliYunRes>()
-    .await.map_err(|_| TransError::ResponseError)?
+    .await.map_err(send_error)?;
+  let date = server_date(&response);
+  let response = read_json::<AliYunRes>(response, error)
+    .await.map_err(|error| context.correct_clock(error, date))?
     .to_trans_res();
   return Ok(response);
 }
\ No newline at end of file
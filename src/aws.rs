@@ -0,0 +1,100 @@
+use reqwest::{StatusCode, Version};
+use reqwest::header::{AUTHORIZATION, HeaderValue};
+use serde::{Serialize, Deserialize};
+use crate::common::{Context, TransReq, TransRes, TransError, TransResult, classify_error, json_text, read_json, retry_on_skew, send_error, server_date, with_account};
+use crate::sign::{CanonicalRequest, V4Signer};
+
+#[derive(Serialize, Debug)]
+pub struct AwsReq {
+  #[serde(rename(serialize = "Text"))]
+  src: String,
+
+  #[serde(rename(serialize = "SourceLanguageCode"))]
+  source: String,
+
+  #[serde(rename(serialize = "TargetLanguageCode"))]
+  target: String,
+}
+
+impl AwsReq {
+  fn create(src: &str, source: &str, target: &str) -> Self {
+    return AwsReq {
+      src: String::from(src),
+      source: String::from(aws_lang(source)),
+      target: String::from(aws_lang(target)),
+    };
+  }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AwsRes {
+  #[serde(rename(deserialize = "TranslatedText"))]
+  dst: String,
+
+  // #[serde(rename(deserialize = "SourceLanguageCode"))]
+  // source: String,
+
+  // #[serde(rename(deserialize = "TargetLanguageCode"))]
+  // target: String,
+}
+
+impl AwsRes {
+  fn to_trans_res(&self) -> TransRes {
+    return TransRes::create(self.dst.clone());
+  }
+}
+
+fn aws_lang(lang: &str) -> &str {
+  return match lang {
+    "zh-CN" | "zh-Hans" | "zh-CHS" => "zh",
+    "cht" | "zh-Hant" | "zh-CHT" => "zh-TW",
+    "jp" => "ja",
+    "kor" => "ko",
+    _ => lang,
+  };
+}
+
+// `{"__type": "InvalidSignatureException", "message": "Signature expired: .."}`
+fn error(status: StatusCode, body: &serde_json::Value) -> TransError {
+  let message = if body["message"].is_null() { &body["Message"] } else { &body["message"] };
+  return classify_error(status, json_text(&body["__type"]).as_str(), json_text(message).as_str());
+}
+
+pub async fn trans(context: &Context, req: &TransReq) -> TransResult<TransRes> {
+  return with_account(context, "aws", context.aws_accounts(), |account| {
+    retry_on_skew(context, move || trans_once(context, req, account))
+  }).await;
+}
+
+async fn trans_once(context: &Context, req: &TransReq, account: usize) -> TransResult<TransRes> {
+  let client = context.client_ref("aws");
+  let current_time = context.now();
+  let host = context.aws_host();
+  let body = AwsReq::create(req.src_ref(), req.source_ref(), req.target_ref());
+  let body_str = serde_json::to_string(&body).map_err(|_| TransError::SerdeError)?;
+
+  let mut sign_request = CanonicalRequest::create("POST", "/", body_str.as_str())
+    .header("content-type", "application/x-amz-json-1.1")
+    .header("host", host.as_str())
+    .header("x-amz-date", V4Signer::timestamp(&current_time).as_str())
+    .header("x-amz-target", "AWSShineFrontendService_20170701.TranslateText");
+  if let Some(token) = context.aws_token(account) {
+    sign_request = sign_request.header("x-amz-security-token", token);
+  }
+  let authorization = V4Signer::aws(context.aws_region(), "translate")
+    .sign(context.aws_key(account)?, context.aws_secret(account)?, &sign_request, &current_time)?;
+  let mut headers = sign_request.header_map().map_err(|_| TransError::AwsConfig)?;
+  headers.insert(AUTHORIZATION, HeaderValue::from_str(authorization.as_str()).unwrap());
+
+  let mut request_builder = client.post(context.aws_address());
+  request_builder = request_builder.version(Version::HTTP_11);
+  request_builder = request_builder.headers(headers);
+  request_builder = request_builder.body(body_str);
+  let response = request_builder.send()
+    .await.map_err(send_error)?;
+  let date = server_date(&response);
+  let response = read_json::<AwsRes>(response, error)
+    .await.map_err(|error| context.correct_clock(error, date))?
+    .to_trans_res();
+  return Ok(response);
+}
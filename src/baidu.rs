@@ -1,6 +1,8 @@
-use reqwest::Version;
+use reqwest::{RequestBuilder, StatusCode, Version};
 use serde::Deserialize;
-use crate::common::{Context, TransRes, TransError, TransResult, hex_byte, md5_str};
+use crate::common::{Context, TransReq, TransRes, TransError, TransResult, hex_byte, md5_str, read_json, send_error, status_error, json_text, with_account};
+
+const BAIDU_BATCH_LENGTH: usize = 6000;
 
 #[derive(Deserialize, Debug)]
 pub struct BaiduRes {
@@ -15,8 +17,14 @@ pub struct BaiduRes {
 }
 
 impl BaiduRes {
+  // one entry per line of `q`
   fn to_trans_res(&self) -> TransRes {
-    return TransRes::create(self.data.first().map(|res| res.dst.clone()).unwrap());
+    let lines: Vec<&str> = self.data.iter().map(|res| res.dst.as_str()).collect();
+    return TransRes::create(lines.join("\n"));
+  }
+
+  fn to_trans_list(&self) -> Vec<TransRes> {
+    return self.data.iter().map(|res| TransRes::create(res.dst.clone())).collect();
   }
 }
 
@@ -27,29 +35,133 @@ struct BaiduInnerRes {
   dst: String,
 }
 
-pub async fn trans(context: & Context) -> TransResult<TransRes> {
-  let req = context.req_ref();
-  let client = context.client_ref();
-  let mut sign_content = String::from(context.baidu_key()?);
+fn request(context: &Context, req: &TransReq, account: usize) -> TransResult<RequestBuilder> {
+  let client = context.client_ref("baidu");
+  let salt = context.nonce();
+  let mut sign_content = String::from(context.baidu_key(account)?);
   sign_content.push_str(req.src_ref());
-  sign_content.push_str("9527");
-  sign_content.push_str(context.baidu_secret()?);
+  sign_content.push_str(salt.as_str());
+  // the vertical domain API signs appid + q + salt + domain + secret
+  if let Some(domain) = context.baidu_domain() {
+    sign_content.push_str(domain);
+  }
+  sign_content.push_str(context.baidu_secret(account)?);
   let sign = hex_byte(md5_str(sign_content.as_str()).as_slice());
-  let query = [
+  let mut query = vec![
     ("q", req.src_ref()),
     ("from", "auto"),
     ("to", req.target_ref()),
-    ("salt", "9527"),
-    ("appid", context.baidu_key()?),
+    ("salt", salt.as_str()),
+    ("appid", context.baidu_key(account)?),
     ("sign", sign.as_ref())
   ];
+  if let Some(domain) = context.baidu_domain() {
+    query.push(("domain", domain));
+  }
   let mut request_builder = client.get(context.baidu_address());
   request_builder = request_builder.version(Version::HTTP_11);
   request_builder = request_builder.query(&query);
-  let response = request_builder.send()
-    .await.map_err(|_| TransError::RequestError)?
-    .json::<BaiduRes>()
-    .await.map_err(|_| TransError::ResponseError)?
+  return Ok(request_builder);
+}
+
+// https://fanyi-api.baidu.com/doc/21 error codes
+fn error(status: StatusCode, body: &serde_json::Value) -> TransError {
+  return match json_text(&body["error_code"]).as_str() {
+    "52003" | "54001" | "58000" | "90107" => TransError::AuthError,
+    "54004" => TransError::QuotaExceeded,
+    "54003" | "54005" => TransError::RateLimited,
+    _ => status_error(status),
+  };
+}
+
+pub async fn trans(context: &Context, req: &TransReq) -> TransResult<TransRes> {
+  return with_account(context, "baidu", context.baidu_accounts(), |account| trans_once(context, req, account)).await;
+}
+
+async fn trans_once(context: &Context, req: &TransReq, account: usize) -> TransResult<TransRes> {
+  let response = request(context, req, account)?.send()
+    .await.map_err(send_error)?;
+  let response = read_json::<BaiduRes>(response, error)
+    .await?
     .to_trans_res();
   return Ok(response);
-}
\ No newline at end of file
+}
+
+// segments are joined with newlines into one `q` of at most 6000 bytes, every line comes back
+// as its own `trans_result`, so segments can't contain newlines themselves
+pub async fn trans_batch(context: &Context, segments: &[String]) -> TransResult<Vec<TransRes>> {
+  let req = context.req_ref();
+  // baidu drops blank lines, they are translated to themselves
+  let texts: Vec<&String> = segments.iter().filter(|segment| !segment.trim().is_empty()).collect();
+  let mut translated = Vec::with_capacity(texts.len());
+  let mut start = 0;
+  while start < texts.len() {
+    let mut end = start + 1;
+    let mut length = texts[start].len();
+    while end < texts.len() && length + 1 + texts[end].len() <= BAIDU_BATCH_LENGTH {
+      length += 1 + texts[end].len();
+      end += 1;
+    }
+    let lines: Vec<&str> = texts[start..end].iter().map(|text| text.as_str()).collect();
+    let chunk = TransReq::create(lines.join("\n").as_str(), req.source_ref(), req.target_ref());
+    let chunk = &chunk;
+    let list = with_account(context, "baidu", context.baidu_accounts(), |account| async move {
+      let response = request(context, chunk, account)?.send()
+        .await.map_err(send_error)?;
+      return Ok(read_json::<BaiduRes>(response, error).await?.to_trans_list());
+    }).await?;
+    if list.len() != end - start {
+      return Err(TransError::ResponseError);
+    }
+    translated.extend(list);
+    start = end;
+  }
+  let mut translated = translated.into_iter();
+  return segments.iter()
+    .map(|segment| match segment.trim().is_empty() {
+      true => Ok(TransRes::create(segment.clone())),
+      false => translated.next().ok_or(TransError::ResponseError),
+    })
+    .collect();
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{TimeZone, Utc};
+  use reqwest::Client;
+  use crate::common::{Config, FixedClock, TransReq};
+  use super::*;
+
+  fn context(nonce: &str) -> Context {
+    return context_with(nonce, "");
+  }
+
+  fn context_with(nonce: &str, options: &str) -> Context {
+    let content = format!("{}\n[baidu]\nkey = \"2015063000000001\"\nsecret = \"12345678\"", options);
+    let config: Config = toml::from_str(content.as_str()).unwrap();
+    let clock = FixedClock::create(Utc.timestamp_opt(1664611200, 0).unwrap(), nonce);
+    return Context::create(TransReq::create("apple", "auto", "zh"), Client::new(), config).with_clock(clock);
+  }
+
+  // https://fanyi-api.baidu.com/doc/21, appid + q + salt + secret
+  #[test]
+  fn signed_request_is_reproducible() {
+    let (first, second) = (context("1435660288"), context("1435660288"));
+    let first = request(&first, first.req_ref(), 0).unwrap().build().unwrap();
+    let second = request(&second, second.req_ref(), 0).unwrap().build().unwrap();
+    assert_eq!(first.url(), second.url());
+    assert!(first.url().query().unwrap().contains("salt=1435660288&appid=2015063000000001&sign=f89f9594663708c1605f3d736d01d2d4"));
+    let other = context("1435660289");
+    let other = request(&other, other.req_ref(), 0).unwrap().build().unwrap();
+    assert_ne!(first.url(), other.url());
+  }
+
+  // generic `domain = "medicine"` selects the `senimed` vertical domain
+  #[test]
+  fn domain_uses_field_translate() {
+    let context = context_with("1435660288", "domain = \"medicine\"");
+    let request = request(&context, context.req_ref(), 0).unwrap().build().unwrap();
+    assert_eq!(request.url().path(), "/api/trans/vip/fieldtranslate");
+    assert!(request.url().query().unwrap().ends_with("sign=c00eb2c63167b9bcc70ca81a5541fd0b&domain=senimed"));
+  }
+}
@@ -0,0 +1,66 @@
+use std::future::Future;
+use futures::StreamExt;
+use crate::common::{Context, TransReq, TransRes, TransResult, retry_on_rate_limit};
+use crate::{aliyun, baidu, chunk, huoshan, tencent, trans_engine};
+
+// single requests in flight per engine when there is no batch API
+const BATCH_CONCURRENCY: usize = 4;
+
+// `--batch` and `--each` segments with the built-in engine `name`, one result per segment
+pub async fn trans_batch(name: &str, context: &Context, segments: &[String]) -> Vec<TransResult<TransRes>> {
+  // segments over the limit are split by single calls
+  let fits = chunk::limit(name).is_none_or(|limit| segments.iter().all(|segment| limit.fits(segment)));
+  let native = match name {
+    _ if !fits => None,
+    "huoshan" => Some(retry_on_rate_limit(|| huoshan::trans_batch(context, segments)).await),
+    "tencent" => Some(retry_on_rate_limit(|| tencent::trans_batch(context, segments)).await),
+    "aliyun" if context.aliyun_api() == "rpc" => Some(retry_on_rate_limit(|| aliyun::trans_batch(context, segments)).await),
+    "baidu" if !segments.iter().any(|segment| segment.contains('\n')) => Some(retry_on_rate_limit(|| baidu::trans_batch(context, segments)).await),
+    _ => None,
+  };
+  return match native {
+    Some(Ok(results)) => results.into_iter().map(Ok).collect(),
+    Some(Err(error)) => segments.iter().map(|_| Err(error.clone())).collect(),
+    None => trans_each(context, segments, |req| async move { trans_engine(name, context, &req).await }).await,
+  };
+}
+
+// one request per segment, at most `BATCH_CONCURRENCY` at a time, results keep the segment order
+pub async fn trans_each<F, Fut>(context: &Context, segments: &[String], trans: F) -> Vec<TransResult<TransRes>>
+  where
+    F: Fn(TransReq) -> Fut,
+    Fut: Future<Output=TransResult<TransRes>>,
+{
+  let req = context.req_ref();
+  // futures do nothing until polled, `buffered` starts them in order
+  let requests: Vec<Fut> = segments.iter()
+    .map(|segment| trans(TransReq::create(segment.as_str(), req.source_ref(), req.target_ref())))
+    .collect();
+  return futures::stream::iter(requests)
+    .buffered(BATCH_CONCURRENCY)
+    .collect()
+    .await;
+}
+
+// one segment per non-blank line, or the strings of a JSON array
+pub fn parse_segments(content: &str) -> Vec<String> {
+  if let Ok(segments) = serde_json::from_str::<Vec<String>>(content.trim()) {
+    return segments;
+  }
+  return content.lines()
+    .filter(|line| !line.trim().is_empty())
+    .map(String::from)
+    .collect();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn segments_from_lines_or_json() {
+    assert_eq!(parse_segments("apple\n\n  \nbanana tree\r\n"), vec!["apple", "banana tree"]);
+    assert_eq!(parse_segments(" [\"apple\", \"a\\nb\"]\n"), vec!["apple", "a\nb"]);
+    assert_eq!(parse_segments("[not json\n"), vec!["[not json"]);
+  }
+}
@@ -0,0 +1,81 @@
+use reqwest::{StatusCode, Version};
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use serde::{Serialize, Deserialize};
+use crate::common::{Context, TransReq, TransRes, TransError, TransResult, classify_error, json_text, read_json, send_error, with_account};
+
+#[derive(Serialize, Debug)]
+pub struct CaiYunReq {
+  source: Vec<String>,
+
+  trans_type: String,
+
+  request_id: String,
+
+  detect: bool,
+}
+
+impl CaiYunReq {
+  fn create(src: &str, source: &str, target: &str) -> Self {
+    return CaiYunReq {
+      source: vec![String::from(src)],
+      trans_type: format!("{}2{}", caiyun_lang(source), caiyun_lang(target)),
+      request_id: String::from("translator"),
+      detect: true,
+    };
+  }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CaiYunRes {
+  #[serde(rename(deserialize = "target"))]
+  data: Vec<String>,
+}
+
+impl CaiYunRes {
+  fn to_trans_res(&self) -> TransResult<TransRes> {
+    return self.data.first()
+      .map(|dst| TransRes::create(dst.clone()))
+      .ok_or(TransError::ResponseError);
+  }
+}
+
+fn caiyun_lang(lang: &str) -> &str {
+  return match lang {
+    "zh-CN" | "zh-Hans" | "zh-CHS" => "zh",
+    "cht" | "zh-TW" | "zh-CHT" => "zh-Hant",
+    "jp" => "ja",
+    "kor" => "ko",
+    _ => lang,
+  };
+}
+
+// `{"message": "Invalid token"}`
+fn error(status: StatusCode, body: &serde_json::Value) -> TransError {
+  return classify_error(status, "", json_text(&body["message"]).as_str());
+}
+
+pub async fn trans(context: &Context, req: &TransReq) -> TransResult<TransRes> {
+  return with_account(context, "caiyun", context.caiyun_accounts(), |account| trans_once(context, req, account)).await;
+}
+
+async fn trans_once(context: &Context, req: &TransReq, account: usize) -> TransResult<TransRes> {
+  let client = context.client_ref("caiyun");
+  let body = CaiYunReq::create(req.src_ref(), req.source_ref(), req.target_ref());
+  let body_str = serde_json::to_string(&body).map_err(|_| TransError::SerdeError)?;
+  let authorization = format!("token {}", context.caiyun_token(account)?);
+
+  let mut headers = HeaderMap::with_capacity(2);
+  headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
+  headers.insert(HeaderName::from_static("x-authorization"), HeaderValue::from_str(authorization.as_str()).map_err(|_| TransError::CaiYunConfig)?);
+
+  let mut request_builder = client.post(context.caiyun_address());
+  request_builder = request_builder.version(Version::HTTP_11);
+  request_builder = request_builder.headers(headers);
+  request_builder = request_builder.body(body_str);
+  let response = request_builder.send()
+    .await.map_err(send_error)?;
+  let response = read_json::<CaiYunRes>(response, error)
+    .await?
+    .to_trans_res()?;
+  return Ok(response);
+}
@@ -0,0 +1,213 @@
+use std::time::Duration;
+use crate::common::{Context, TransError, TransReq, TransRes, TransResult, retry_on_rate_limit};
+use crate::{huoshan, trans_single};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Limit {
+  Bytes(usize),
+  Chars(usize),
+}
+
+impl Limit {
+  fn measure(&self, text: &str) -> usize {
+    return match self {
+      Limit::Bytes(_) => text.len(),
+      Limit::Chars(_) => text.chars().count(),
+    };
+  }
+
+  fn max(&self) -> usize {
+    return match self {
+      Limit::Bytes(max) | Limit::Chars(max) => *max,
+    };
+  }
+
+  pub fn fits(&self, text: &str) -> bool {
+    return self.measure(text) <= self.max();
+  }
+}
+
+// the longest text a built-in engine takes in one request
+pub fn limit(name: &str) -> Option<Limit> {
+  return match name {
+    "baidu" => Some(Limit::Bytes(6000)),
+    "tencent" => Some(Limit::Chars(6000)),
+    "huoshan" | "aliyun" | "youdao" | "niutrans" => Some(Limit::Chars(5000)),
+    "aws" => Some(Limit::Bytes(10000)),
+    _ => None,
+  };
+}
+
+// pause between the chunks of one text, below the default QPS of each service
+pub fn interval(name: &str) -> Duration {
+  return match name {
+    "baidu" => Duration::from_millis(1000),
+    "tencent" | "youdao" => Duration::from_millis(200),
+    "aliyun" | "niutrans" => Duration::from_millis(100),
+    _ => Duration::ZERO,
+  };
+}
+
+// `text` cut into pieces that fit `limit` and concatenate back to `text`, cuts go after
+// paragraphs and sentences, a single sentence over the limit is cut at a space or anywhere
+pub fn split(text: &str, limit: Limit) -> Vec<&str> {
+  let mut chunks = Vec::new();
+  let mut start = 0;
+  let mut end = 0;
+  for sentence in sentences(text) {
+    let next = end + sentence.len();
+    if limit.fits(&text[start..next]) {
+      end = next;
+      continue;
+    }
+    if end > start {
+      chunks.push(&text[start..end]);
+      start = end;
+    }
+    if limit.fits(sentence) {
+      end = next;
+      continue;
+    }
+    let mut rest = sentence;
+    while !limit.fits(rest) {
+      let (head, tail) = rest.split_at(cut(rest, limit));
+      chunks.push(head);
+      rest = tail;
+    }
+    start = next - rest.len();
+    end = next;
+  }
+  if end > start {
+    chunks.push(&text[start..end]);
+  }
+  return chunks;
+}
+
+// sentences with their terminator and the whitespace after it, a line break ends one too
+fn sentences(text: &str) -> Vec<&str> {
+  let mut sentences = Vec::new();
+  let mut start = 0;
+  let mut chars = text.char_indices().peekable();
+  while let Some((_, c)) = chars.next() {
+    let next = chars.peek().map(|(_, next)| *next);
+    let ends = match c {
+      '\n' | '。' | '！' | '？' | '；' => true,
+      '.' | '!' | '?' | ';' => next.is_none_or(char::is_whitespace),
+      _ => false,
+    };
+    if !ends {
+      continue;
+    }
+    while chars.next_if(|(_, next)| next.is_whitespace()).is_some() {}
+    let end = chars.peek().map_or(text.len(), |(index, _)| *index);
+    sentences.push(&text[start..end]);
+    start = end;
+  }
+  if start < text.len() {
+    sentences.push(&text[start..]);
+  }
+  return sentences;
+}
+
+// the longest prefix of `text` that fits, ending after a space when there is one
+fn cut(text: &str, limit: Limit) -> usize {
+  let mut end = 0;
+  let mut space = None;
+  for (index, c) in text.char_indices() {
+    let next = index + c.len_utf8();
+    if !limit.fits(&text[..next]) {
+      break;
+    }
+    end = next;
+    if c.is_whitespace() {
+      space = Some(next);
+    }
+  }
+  // a limit below one character still has to make progress
+  if end == 0 {
+    return text.chars().next().map_or(text.len(), char::len_utf8);
+  }
+  return space.unwrap_or(end);
+}
+
+// whitespace around a chunk isn't sent, it is put back around the translation
+pub fn surrounding(chunk: &str) -> (&str, &str, &str) {
+  let body = chunk.trim();
+  let lead = &chunk[..chunk.len() - chunk.trim_start().len()];
+  let trail = &chunk[lead.len() + body.len()..];
+  return (lead, body, trail);
+}
+
+// `req` over the `limit` of the built-in engine `name`, translated chunk by chunk and
+// put back together in order
+pub async fn trans(name: &str, context: &Context, req: &TransReq, limit: Limit) -> TransResult<TransRes> {
+  let chunks = split(req.src_ref(), limit);
+  if name == "huoshan" {
+    return trans_list(context, chunks.as_slice()).await;
+  }
+  let mut result = String::new();
+  for (index, chunk) in chunks.iter().enumerate() {
+    let (lead, body, trail) = surrounding(chunk);
+    result.push_str(lead);
+    if !body.is_empty() {
+      if index > 0 {
+        tokio::time::sleep(interval(name)).await;
+      }
+      let chunk_req = TransReq::create(body, req.source_ref(), req.target_ref());
+      result.push_str(trans_retrying(name, context, &chunk_req).await?.result());
+    }
+    result.push_str(trail);
+  }
+  return Ok(TransRes::create(result));
+}
+
+// huoshan takes the chunks as one `TextList`
+async fn trans_list(context: &Context, chunks: &[&str]) -> TransResult<TransRes> {
+  let parts: Vec<(&str, &str, &str)> = chunks.iter().map(|chunk| surrounding(chunk)).collect();
+  let bodies: Vec<String> = parts.iter()
+    .filter(|(_, body, _)| !body.is_empty())
+    .map(|(_, body, _)| String::from(*body))
+    .collect();
+  let mut translated = retry_on_rate_limit(|| huoshan::trans_batch(context, bodies.as_slice())).await?.into_iter();
+  let mut result = String::new();
+  for (lead, body, trail) in parts {
+    result.push_str(lead);
+    if !body.is_empty() {
+      result.push_str(translated.next().ok_or(TransError::ResponseError)?.result());
+    }
+    result.push_str(trail);
+  }
+  return Ok(TransRes::create(result));
+}
+
+// a chunk rejected by the rate limit is sent again, an exhausted quota fails the text
+async fn trans_retrying(name: &str, context: &Context, req: &TransReq) -> TransResult<TransRes> {
+  return retry_on_rate_limit(|| trans_single(name, context, req)).await;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn chunks_end_at_sentences() {
+    let text = "First one. Second one!\n\n第一句。第二句？Third";
+    assert_eq!(sentences(text), vec!["First one. ", "Second one!\n\n", "第一句。", "第二句？", "Third"]);
+    assert_eq!(sentences("pi is 3.14 e.g. this"), vec!["pi is 3.14 e.g. ", "this"]);
+
+    let chunks = split(text, Limit::Chars(24));
+    assert_eq!(chunks, vec!["First one. Second one!\n\n", "第一句。第二句？Third"]);
+    assert_eq!(chunks.concat(), text);
+    assert_eq!(split(text, Limit::Bytes(12)), vec!["First one. ", "Second one!\n", "\n", "第一句。", "第二句？", "Third"]);
+  }
+
+  #[test]
+  fn long_sentences_are_cut() {
+    let text = "aaaa bbbb cccc dddd";
+    let chunks = split(text, Limit::Chars(10));
+    assert_eq!(chunks, vec!["aaaa bbbb ", "cccc dddd"]);
+    let chunks = split("一二三四五六七", Limit::Bytes(7));
+    assert_eq!(chunks, vec!["一二", "三四", "五六", "七"]);
+    assert_eq!(surrounding("\n  text \n"), ("\n  ", "text", " \n"));
+  }
+}
@@ -0,0 +1,33 @@
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use crate::common::{CommandConfig, Context, TransReq, TransRes, TransError, TransResult};
+
+// The request is written to stdin as `{"src": .., "source": .., "target": ..}`,
+// the program answers with a `TransRes` on stdout and exits with code 0.
+pub async fn trans(context: &Context, config: &CommandConfig, req: &TransReq) -> TransResult<TransRes> {
+  let input = serde_json::to_vec(req).map_err(|_| TransError::SerdeError)?;
+  let mut child = Command::new(config.program())
+    .args(config.args())
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .kill_on_drop(true)
+    .spawn()
+    .map_err(|_| TransError::CommandError)?;
+  let mut stdin = child.stdin.take().ok_or(TransError::CommandError)?;
+  let write = async move {
+    let res = stdin.write_all(input.as_slice()).await;
+    drop(stdin);
+    return res;
+  };
+  let execute = async { tokio::join!(write, child.wait_with_output()) };
+  let (write, output) = tokio::time::timeout(Duration::from_secs(config.timeout(context.config_ref().timeout())), execute)
+    .await.map_err(|_| TransError::CommandTimeout)?;
+  let output = output.map_err(|_| TransError::CommandError)?;
+  if !output.status.success() || write.is_err() {
+    return Err(TransError::CommandError);
+  }
+  return serde_json::from_slice::<TransRes>(output.stdout.as_slice()).map_err(|_| TransError::ResponseError);
+}
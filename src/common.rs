@@ -1,8 +1,15 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use chrono::{DateTime, Duration, Utc};
 use hmac::{Hmac, Mac};
-use reqwest::Client;
-use serde::Deserialize;
+use rand::Rng;
+use reqwest::{Certificate, Client, Proxy, Response, StatusCode, Url};
+use reqwest::header::DATE;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TransError {
   RequestError,
   ResponseError,
@@ -11,6 +18,31 @@ pub enum TransError {
   TencentConfig,
   HuoShanConfig,
   AliYunConfig,
+  YoudaoConfig,
+  CaiYunConfig,
+  NiuTransConfig,
+  AwsConfig,
+  CustomConfig,
+  CommandError,
+  CommandTimeout,
+  DictConfig,
+  DictNotFound,
+  CredentialError,
+  SecretStoreError,
+  PassphraseMismatch,
+  ProfileNotFound,
+  InitError,
+  InputError,
+  OutputError,
+  EngineNotSelected,
+  HttpConfig,
+  AuthError,
+  ClockSkew,
+  QuotaExceeded,
+  RateLimited,
+  UnsupportedRegion,
+  DnsError,
+  TlsError,
   SerdeError,
   HmacError,
   ChannelError
@@ -18,6 +50,143 @@ pub enum TransError {
 
 pub type TransResult<T> = Result<T, TransError>;
 
+// Tells name resolution and TLS failures apart from other failed requests,
+// reqwest only exposes them through the messages of the source chain.
+pub fn send_error(error: reqwest::Error) -> TransError {
+  let mut source: Option<&dyn std::error::Error> = Some(&error);
+  while let Some(inner) = source {
+    let message = inner.to_string().to_lowercase();
+    if message.contains("dns error") || message.contains("failed to lookup address") {
+      return TransError::DnsError;
+    }
+    if message.contains("certificate") || message.contains("tls") || message.contains("ssl") || message.contains("handshake") {
+      return TransError::TlsError;
+    }
+    source = inner.source();
+  }
+  return TransError::RequestError;
+}
+
+pub fn status_error(status: StatusCode) -> TransError {
+  return match status {
+    StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => TransError::AuthError,
+    StatusCode::TOO_MANY_REQUESTS => TransError::RateLimited,
+    StatusCode::PAYMENT_REQUIRED => TransError::QuotaExceeded,
+    _ => TransError::ResponseError,
+  };
+}
+
+// Maps the textual error code and message of a cloud API, e.g. Tencent
+// `AuthFailure.SignatureExpire` or AWS `ThrottlingException`.
+pub fn classify_error(status: StatusCode, code: &str, message: &str) -> TransError {
+  let text = format!("{} {}", code, message).to_lowercase();
+  let matches = |words: &[&str]| words.iter().any(|word| text.contains(word));
+  if matches(&["timestamp", "signatureexpire", "signature expired", "requestexpired", "skew", "not yet current"]) {
+    return TransError::ClockSkew;
+  }
+  if matches(&["quota", "insufficient", "balance", "arrear", "nofreeamount", "isolate"]) {
+    return TransError::QuotaExceeded;
+  }
+  if matches(&["throttl", "limitexceeded", "limit exceeded", "frequency", "too many requests"]) {
+    return TransError::RateLimited;
+  }
+  if matches(&["region"]) {
+    return TransError::UnsupportedRegion;
+  }
+  if matches(&["auth", "signature", "accesskey", "access key", "apikey", "credential", "secretid", "token", "forbidden", "denied", "unrecognizedclient"]) {
+    return TransError::AuthError;
+  }
+  return status_error(status);
+}
+
+pub fn server_date(response: &Response) -> Option<DateTime<Utc>> {
+  let date = response.headers().get(DATE)?.to_str().ok()?;
+  return DateTime::parse_from_rfc2822(date).ok().map(|date| date.with_timezone(&Utc));
+}
+
+// retries of a request rejected by the rate limit, waiting one more second each time
+const RATE_LIMIT_RETRIES: u64 = 2;
+
+// Sends again after one, then two seconds while the provider reports too many requests.
+pub async fn retry_on_rate_limit<T, F, Fut>(send: F) -> TransResult<T>
+  where
+    F: Fn() -> Fut,
+    Fut: Future<Output=TransResult<T>>,
+{
+  let mut attempt = 0;
+  loop {
+    let res = send().await;
+    if !matches!(res, Err(TransError::RateLimited)) || attempt == RATE_LIMIT_RETRIES {
+      return res;
+    }
+    attempt += 1;
+    tokio::time::sleep(std::time::Duration::from_secs(attempt)).await;
+  }
+}
+
+// Sends once more when the first attempt taught the context the server's clock.
+pub async fn retry_on_skew<T, F, Fut>(context: &Context, send: F) -> TransResult<T>
+  where
+    F: Fn() -> Fut,
+    Fut: Future<Output=TransResult<T>>,
+{
+  let skew = context.clock_skew();
+  let res = send().await;
+  if matches!(res, Err(TransError::ClockSkew)) && context.clock_skew() != skew {
+    return send().await;
+  }
+  return res;
+}
+
+// Sends with the account picked for `provider`, an account out of quota hands
+// the request on to the next one until every account was tried. Being rate
+// limited says nothing about the quota, it is returned as is.
+pub async fn with_account<T, F, Fut>(context: &Context, provider: &'static str, accounts: (usize, Rotation), send: F) -> TransResult<T>
+  where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output=TransResult<T>>,
+{
+  let (count, _) = accounts;
+  let first = context.next_account(provider, accounts);
+  let mut res = Err(TransError::QuotaExceeded);
+  for attempt in 0..count {
+    let account = (first + attempt) % count;
+    res = send(account).await;
+    if !matches!(res, Err(TransError::QuotaExceeded)) {
+      break;
+    }
+    context.skip_account(provider, account);
+  }
+  return res;
+}
+
+// Parses a successful response as `T`, anything else is handed to `error`
+// together with the status to pick the most specific error.
+pub async fn read_json<T>(response: Response, error: fn(StatusCode, &serde_json::Value) -> TransError) -> TransResult<T>
+  where
+    T: DeserializeOwned,
+{
+  let status = response.status();
+  let body = response.bytes().await.map_err(send_error)?;
+  if let Ok(res) = serde_json::from_slice::<T>(&body) {
+    return Ok(res);
+  }
+  return match serde_json::from_slice::<serde_json::Value>(&body) {
+    Ok(value) => Err(error(status, &value)),
+    Err(_) => Err(status_error(status)),
+  };
+}
+
+// a JSON string or number as text, providers aren't consistent about it
+pub fn json_text(value: &serde_json::Value) -> String {
+  return match value {
+    serde_json::Value::String(text) => text.clone(),
+    serde_json::Value::Null => String::new(),
+    other => other.to_string(),
+  };
+}
+
+#[derive(Serialize)]
 pub struct TransReq {
   src: String,
 
@@ -50,27 +219,107 @@ impl<'a> TransReq {
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct TransRes {
   result: String,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  phonetic: Option<String>,
+
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  explains: Vec<String>,
 }
 
 impl TransRes {
   pub fn create(result: String) -> Self {
     return TransRes {
-      result
+      result,
+      phonetic: None,
+      explains: Vec::new(),
     };
   }
 
+  pub fn with_phonetic(mut self, phonetic: String) -> Self {
+    self.phonetic = Some(phonetic);
+    return self;
+  }
+
+  pub fn with_explains(mut self, explains: Vec<String>) -> Self {
+    self.explains.extend(explains);
+    return self;
+  }
+
   pub fn result(&self) -> &str {
     return self.result.as_str();
   }
+
+  pub fn phonetic(&self) -> Option<&str> {
+    return self.phonetic.as_deref();
+  }
+
+  pub fn explains(&self) -> &[String] {
+    return self.explains.as_slice();
+  }
+}
+
+// Source of the timestamps, salts and nonces that end up in signed requests.
+pub trait Clock: Send + Sync {
+  fn now(&self) -> DateTime<Utc>;
+
+  fn nonce(&self) -> String;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> DateTime<Utc> {
+    return Utc::now();
+  }
+
+  fn nonce(&self) -> String {
+    return rand::thread_rng().gen::<u64>().to_string();
+  }
+}
+
+// Makes every signed request byte-for-byte reproducible.
+#[cfg(test)]
+pub struct FixedClock {
+  time: DateTime<Utc>,
+  nonce: String,
+}
+
+#[cfg(test)]
+impl FixedClock {
+  pub fn create(time: DateTime<Utc>, nonce: &str) -> Self {
+    return FixedClock {
+      time,
+      nonce: String::from(nonce),
+    };
+  }
+}
+
+#[cfg(test)]
+impl Clock for FixedClock {
+  fn now(&self) -> DateTime<Utc> {
+    return self.time;
+  }
+
+  fn nonce(&self) -> String {
+    return self.nonce.clone();
+  }
 }
 
 pub struct Context {
   req: TransReq,
   client: Client,
   config: Config,
+  clock: Box<dyn Clock>,
+  // server time minus local time, learned from a rejected signature
+  skew: Mutex<Option<Duration>>,
+  // next account per provider
+  accounts: Mutex<HashMap<&'static str, usize>>,
+  // engines with their own HTTP options, the others share `client`
+  clients: HashMap<String, Client>,
 }
 
 impl Context {
@@ -78,122 +327,882 @@ impl Context {
     return Context {
       req,
       client,
-      config
+      config,
+      clock: Box::new(SystemClock),
+      skew: Mutex::new(None),
+      accounts: Mutex::new(HashMap::new()),
+      clients: HashMap::new(),
     };
   }
 
+  // builds the HTTP clients from the config, `timeout` unless the config sets one
+  pub fn configure(req: TransReq, config: Config, timeout: Option<u64>) -> TransResult<Self> {
+    let timeout = config.timeout().or(timeout);
+    let mut clients = HashMap::new();
+    for (engine, http) in config.engine_http() {
+      if !http.is_empty() {
+        clients.insert(engine, http_client(&http.or(&config.http), timeout)?);
+      }
+    }
+    let client = http_client(&config.http, timeout)?;
+    let mut context = Context::create(req, client, config);
+    context.clients = clients;
+    return Ok(context);
+  }
+
+  #[cfg(test)]
+  pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+    self.clock = Box::new(clock);
+    return self;
+  }
+
+  pub fn now(&self) -> DateTime<Utc> {
+    return self.clock.now() + self.clock_skew().unwrap_or_else(Duration::zero);
+  }
+
+  pub fn clock_skew(&self) -> Option<Duration> {
+    return *self.skew.lock().unwrap();
+  }
+
+  // Passes `error` through, when it's a rejected request time the offset to
+  // the server's `Date` is remembered so later signatures use its clock.
+  pub fn correct_clock(&self, error: TransError, date: Option<DateTime<Utc>>) -> TransError {
+    if let (TransError::ClockSkew, Some(date)) = (&error, date) {
+      let skew = date - self.clock.now();
+      *self.skew.lock().unwrap() = Some(skew);
+      eprintln!("{}", console::style(format!("local clock is off by {}s, signing with the server time", skew.num_seconds())).yellow());
+    }
+    return error;
+  }
+
+  pub fn next_account(&self, provider: &'static str, (count, rotation): (usize, Rotation)) -> usize {
+    let mut accounts = self.accounts.lock().unwrap();
+    let cursor = accounts.entry(provider).or_insert(0);
+    let account = *cursor % count;
+    if rotation == Rotation::RoundRobin {
+      *cursor = account + 1;
+    }
+    return account;
+  }
+
+  // later requests start after an account that ran out of quota
+  pub fn skip_account(&self, provider: &'static str, account: usize) {
+    let mut accounts = self.accounts.lock().unwrap();
+    let cursor = accounts.entry(provider).or_insert(0);
+    if *cursor == account {
+      *cursor = account + 1;
+    }
+  }
+
+  // `--domain`, mapped to the closest option of each engine, `general` means none
+  pub fn domain(&self) -> Option<&str> {
+    return self.config.domain.as_deref().filter(|domain| *domain != "general");
+  }
+
+  // the built-in engine called `name` has a section and is selected
+  pub fn builtin_enabled(&self, name: &str) -> bool {
+    return match name {
+      "baidu" => self.baidu_enabled(),
+      "huoshan" => self.huoshan_enabled(),
+      "aliyun" => self.aliyun_enabled(),
+      "tencent" => self.tencent_enabled(),
+      "youdao" => self.youdao_enabled(),
+      "caiyun" => self.caiyun_enabled(),
+      "niutrans" => self.niutrans_enabled(),
+      "aws" => self.aws_enabled(),
+      "dict" => self.dict_enabled(),
+      _ => false,
+    };
+  }
+
+  // every configured engine is enabled unless `engines` lists a subset
+  pub fn engine_enabled(&self, name: &str) -> bool {
+    return self.config.engines.as_ref()
+      .map(|engines| engines.iter().any(|engine| engine.eq_ignore_ascii_case(name)))
+      .unwrap_or(true);
+  }
+
+  pub fn nonce(&self) -> String {
+    return self.clock.nonce();
+  }
+
   pub fn req_ref(&self) -> &TransReq {
     return &self.req;
   }
 
-  pub fn client_ref(&self) -> &Client {
-    return &self.client;
+  pub fn client_ref(&self, engine: &str) -> &Client {
+    return self.clients.get(engine).unwrap_or(&self.client);
   }
 }
 
-const BAIDU_ADDRESS: &'static str = "https://fanyi-api.baidu.com/api/trans/vip/translate";
-const ALIYUN_ADDRESS: &'static str = "https://mt.cn-hangzhou.aliyuncs.com/api/translate/web/general";
-const HUOSHAN_ADDRESS: &'static str = "https://open.volcengineapi.com";
-const TENCENT_ADDRESS: &'static str = "https://tmt.tencentcloudapi.com";
+const BAIDU_ADDRESS: &str = "https://fanyi-api.baidu.com/api/trans/vip/translate";
+const BAIDU_FIELD_ADDRESS: &str = "https://fanyi-api.baidu.com/api/trans/vip/fieldtranslate";
+const ALIYUN_ADDRESS: &str = "https://mt.cn-hangzhou.aliyuncs.com/api/translate/web/general";
+const ALIYUN_RPC_ADDRESS: &str = "https://mt.cn-hangzhou.aliyuncs.com";
+const HUOSHAN_ADDRESS: &str = "https://open.volcengineapi.com";
+const TENCENT_ADDRESS: &str = "https://tmt.tencentcloudapi.com";
+const YOUDAO_ADDRESS: &str = "https://openapi.youdao.com/api";
+const CAIYUN_ADDRESS: &str = "https://api.interpreter.caiyunai.com/v1/translator";
+const NIUTRANS_ADDRESS: &str = "https://api.niutrans.com/NiuTransServer/translation";
+const AWS_DEFAULT_REGION: &str = "us-east-1";
 
 impl<'a> Context {
   pub fn baidu_enabled(&self) -> bool {
-    return self.config.baidu.is_some();
+    return self.config.baidu.is_some() && self.engine_enabled("baidu");
   }
 
   pub fn baidu_address(&'a self) -> &'a str {
+    if self.baidu_domain().is_some() {
+      return BAIDU_FIELD_ADDRESS;
+    }
     return BAIDU_ADDRESS;
   }
 
-  pub fn baidu_key(&'a self) -> TransResult<&'a str> {
-    return self.config.baidu.as_ref().map(|c| c.key.as_str()).ok_or(TransError::BaiduConfig);
+  // vertical domains of https://fanyi-api.baidu.com/doc/22
+  pub fn baidu_domain(&'a self) -> Option<&'a str> {
+    if let Some(domain) = self.config.baidu.as_ref().and_then(|c| c.domain.as_deref()) {
+      return Some(domain).filter(|domain| *domain != "general");
+    }
+    return self.domain().and_then(|domain| match domain {
+      "medicine" => Some("senimed"),
+      "it" | "finance" | "machinery" | "senimed" | "novel" | "academic" | "aerospace" | "wiki" | "news" | "law" | "contract" => Some(domain),
+      _ => None,
+    });
+  }
+
+  pub fn baidu_key(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.baidu.as_ref().ok_or(TransError::BaiduConfig)?;
+    return account_value(account, Some(config.key.as_str()), config.accounts.as_slice(), Account::key).ok_or(TransError::BaiduConfig);
+  }
+
+  pub fn baidu_secret(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.baidu.as_ref().ok_or(TransError::BaiduConfig)?;
+    return account_value(account, Some(config.secret.expose()), config.accounts.as_slice(), Account::secret).ok_or(TransError::BaiduConfig);
   }
 
-  pub fn baidu_secret(&'a self) -> TransResult<&'a str> {
-    return self.config.baidu.as_ref().map(|c| c.secret.as_str()).ok_or(TransError::BaiduConfig);
+  pub fn baidu_accounts(&self) -> (usize, Rotation) {
+    return self.config.baidu.as_ref().map(|c| (c.accounts.len() + 1, c.rotation)).unwrap_or((1, Rotation::Failover));
   }
 
   pub fn aliyun_enabled(&self) -> bool {
-    return self.config.aliyun.is_some();
+    return self.config.aliyun.is_some() && self.engine_enabled("aliyun");
   }
   pub fn aliyun_address(&'a self) -> &'a str {
     return ALIYUN_ADDRESS;
   }
 
-  pub fn aliyun_key(&'a self) -> TransResult<&'a str> {
-    return self.config.aliyun.as_ref().map(|c| c.key.as_str()).ok_or(TransError::AliYunConfig);
+  pub fn aliyun_rpc_address(&'a self) -> &'a str {
+    return ALIYUN_RPC_ADDRESS;
   }
 
-  pub fn aliyun_secret(&'a self) -> TransResult<&'a str> {
-    return self.config.aliyun.as_ref().map(|c| c.secret.as_str()).ok_or(TransError::AliYunConfig);
+  pub fn aliyun_api(&'a self) -> &'a str {
+    return self.config.aliyun.as_ref().and_then(|c| c.api.as_deref()).unwrap_or("rpc");
+  }
+
+  // the e-commerce edition serves the domain specific scenes
+  pub fn aliyun_action(&'a self) -> &'a str {
+    if let Some(action) = self.config.aliyun.as_ref().and_then(|c| c.action.as_deref()) {
+      return action;
+    }
+    return match self.aliyun_domain_scene() {
+      Some(_) => "TranslateECommerce",
+      None => "TranslateGeneral",
+    };
+  }
+
+  pub fn aliyun_scene(&'a self) -> Option<&'a str> {
+    if let Some(scene) = self.config.aliyun.as_ref().and_then(|c| c.scene.as_deref()) {
+      return Some(scene);
+    }
+    return match self.aliyun_action() {
+      "TranslateGeneral" => Some("general"),
+      "TranslateECommerce" => Some(self.aliyun_domain_scene().unwrap_or("title")),
+      _ => None,
+    };
+  }
+
+  fn aliyun_domain_scene(&self) -> Option<&'static str> {
+    return match self.domain()? {
+      "ecommerce" => Some("title"),
+      "medicine" => Some("medical"),
+      "finance" => Some("finance"),
+      "social" => Some("social"),
+      _ => None,
+    };
+  }
+
+  // `text` or `html`
+  pub fn aliyun_format(&'a self) -> &'a str {
+    return self.config.aliyun.as_ref().and_then(|c| c.format.as_deref()).unwrap_or("text");
+  }
+
+  pub fn aliyun_key(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.aliyun.as_ref().ok_or(TransError::AliYunConfig)?;
+    return account_value(account, Some(config.key.as_str()), config.accounts.as_slice(), Account::key).ok_or(TransError::AliYunConfig);
+  }
+
+  pub fn aliyun_secret(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.aliyun.as_ref().ok_or(TransError::AliYunConfig)?;
+    return account_value(account, Some(config.secret.expose()), config.accounts.as_slice(), Account::secret).ok_or(TransError::AliYunConfig);
+  }
+
+  pub fn aliyun_token(&'a self, account: usize) -> Option<&'a str> {
+    let config = self.config.aliyun.as_ref()?;
+    return account_value(account, config.token.as_ref().map(Secret::expose), config.accounts.as_slice(), Account::token);
+  }
+
+  pub fn aliyun_accounts(&self) -> (usize, Rotation) {
+    return self.config.aliyun.as_ref().map(|c| (c.accounts.len() + 1, c.rotation)).unwrap_or((1, Rotation::Failover));
   }
 
   pub fn huoshan_enabled(&self) -> bool {
-    return self.config.huoshan.is_some();
+    return self.config.huoshan.is_some() && self.engine_enabled("huoshan");
   }
   pub fn huoshan_address(&'a self) -> &'a str {
     return HUOSHAN_ADDRESS;
   }
 
-  pub fn huoshan_key(&'a self) -> TransResult<&'a str> {
-    return self.config.huoshan.as_ref().map(|c| c.key.as_str()).ok_or(TransError::HuoShanConfig);
+  pub fn huoshan_key(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.huoshan.as_ref().ok_or(TransError::HuoShanConfig)?;
+    return account_value(account, Some(config.key.as_str()), config.accounts.as_slice(), Account::key).ok_or(TransError::HuoShanConfig);
+  }
+
+  pub fn huoshan_secret(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.huoshan.as_ref().ok_or(TransError::HuoShanConfig)?;
+    return account_value(account, Some(config.secret.expose()), config.accounts.as_slice(), Account::secret).ok_or(TransError::HuoShanConfig);
+  }
+
+  pub fn huoshan_token(&'a self, account: usize) -> Option<&'a str> {
+    let config = self.config.huoshan.as_ref()?;
+    return account_value(account, config.token.as_ref().map(Secret::expose), config.accounts.as_slice(), Account::token);
   }
 
-  pub fn huoshan_secret(&'a self) -> TransResult<&'a str> {
-    return self.config.huoshan.as_ref().map(|c| c.secret.as_str()).ok_or(TransError::HuoShanConfig);
+  pub fn huoshan_accounts(&self) -> (usize, Rotation) {
+    return self.config.huoshan.as_ref().map(|c| (c.accounts.len() + 1, c.rotation)).unwrap_or((1, Rotation::Failover));
   }
 
   pub fn tencent_enabled(&self) -> bool {
-    return self.config.tencent.is_some();
+    return self.config.tencent.is_some() && self.engine_enabled("tencent");
   }
   pub fn tencent_address(&'a self) -> &'a str {
     return TENCENT_ADDRESS;
   }
 
-  pub fn tencent_key(&'a self) -> TransResult<&'a str> {
-    return self.config.tencent.as_ref().map(|c| c.key.as_str()).ok_or(TransError::TencentConfig);
+  pub fn tencent_project_id(&self) -> i64 {
+    return self.config.tencent.as_ref().and_then(|c| c.project_id).unwrap_or(0);
+  }
+
+  pub fn tencent_key(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.tencent.as_ref().ok_or(TransError::TencentConfig)?;
+    return account_value(account, Some(config.key.as_str()), config.accounts.as_slice(), Account::key).ok_or(TransError::TencentConfig);
+  }
+
+  pub fn tencent_secret(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.tencent.as_ref().ok_or(TransError::TencentConfig)?;
+    return account_value(account, Some(config.secret.expose()), config.accounts.as_slice(), Account::secret).ok_or(TransError::TencentConfig);
+  }
+
+  pub fn tencent_token(&'a self, account: usize) -> Option<&'a str> {
+    let config = self.config.tencent.as_ref()?;
+    return account_value(account, config.token.as_ref().map(Secret::expose), config.accounts.as_slice(), Account::token);
+  }
+
+  pub fn tencent_accounts(&self) -> (usize, Rotation) {
+    return self.config.tencent.as_ref().map(|c| (c.accounts.len() + 1, c.rotation)).unwrap_or((1, Rotation::Failover));
+  }
+
+  pub fn youdao_enabled(&self) -> bool {
+    return self.config.youdao.is_some() && self.engine_enabled("youdao");
+  }
+  pub fn youdao_address(&'a self) -> &'a str {
+    return YOUDAO_ADDRESS;
+  }
+
+  pub fn youdao_domain(&'a self) -> Option<&'a str> {
+    if let Some(domain) = self.config.youdao.as_ref().and_then(|c| c.domain.as_deref()) {
+      return Some(domain).filter(|domain| *domain != "general");
+    }
+    return self.domain().and_then(|domain| match domain {
+      "it" => Some("computers"),
+      "computers" | "medicine" | "finance" | "game" => Some(domain),
+      _ => None,
+    });
+  }
+
+  pub fn youdao_key(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.youdao.as_ref().ok_or(TransError::YoudaoConfig)?;
+    return account_value(account, Some(config.key.as_str()), config.accounts.as_slice(), Account::key).ok_or(TransError::YoudaoConfig);
+  }
+
+  pub fn youdao_secret(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.youdao.as_ref().ok_or(TransError::YoudaoConfig)?;
+    return account_value(account, Some(config.secret.expose()), config.accounts.as_slice(), Account::secret).ok_or(TransError::YoudaoConfig);
+  }
+
+  pub fn youdao_accounts(&self) -> (usize, Rotation) {
+    return self.config.youdao.as_ref().map(|c| (c.accounts.len() + 1, c.rotation)).unwrap_or((1, Rotation::Failover));
+  }
+
+  pub fn caiyun_enabled(&self) -> bool {
+    return self.config.caiyun.is_some() && self.engine_enabled("caiyun");
+  }
+  pub fn caiyun_address(&'a self) -> &'a str {
+    return CAIYUN_ADDRESS;
+  }
+
+  pub fn caiyun_token(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.caiyun.as_ref().ok_or(TransError::CaiYunConfig)?;
+    return account_value(account, Some(config.token.expose()), config.accounts.as_slice(), Account::token).ok_or(TransError::CaiYunConfig);
+  }
+
+  pub fn caiyun_accounts(&self) -> (usize, Rotation) {
+    return self.config.caiyun.as_ref().map(|c| (c.accounts.len() + 1, c.rotation)).unwrap_or((1, Rotation::Failover));
+  }
+
+  pub fn niutrans_enabled(&self) -> bool {
+    return self.config.niutrans.is_some() && self.engine_enabled("niutrans");
+  }
+  pub fn niutrans_address(&'a self) -> &'a str {
+    return NIUTRANS_ADDRESS;
+  }
+
+  pub fn niutrans_key(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.niutrans.as_ref().ok_or(TransError::NiuTransConfig)?;
+    return account_value(account, Some(config.key.expose()), config.accounts.as_slice(), Account::key).ok_or(TransError::NiuTransConfig);
+  }
+
+  pub fn niutrans_accounts(&self) -> (usize, Rotation) {
+    return self.config.niutrans.as_ref().map(|c| (c.accounts.len() + 1, c.rotation)).unwrap_or((1, Rotation::Failover));
+  }
+
+  pub fn aws_enabled(&self) -> bool {
+    return self.config.aws.is_some() && self.engine_enabled("aws");
+  }
+
+  pub fn aws_host(&self) -> String {
+    return format!("translate.{}.amazonaws.com", self.aws_region());
+  }
+
+  pub fn aws_address(&self) -> String {
+    return format!("https://{}/", self.aws_host());
   }
 
-  pub fn tencent_secret(&'a self) -> TransResult<&'a str> {
-    return self.config.tencent.as_ref().map(|c| c.secret.as_str()).ok_or(TransError::TencentConfig);
+  pub fn aws_region(&'a self) -> &'a str {
+    return self.config.aws.as_ref().and_then(|c| c.region.as_deref()).unwrap_or(AWS_DEFAULT_REGION);
   }
 
+  pub fn aws_key(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.aws.as_ref().ok_or(TransError::AwsConfig)?;
+    return account_value(account, Some(config.key.as_str()), config.accounts.as_slice(), Account::key).ok_or(TransError::AwsConfig);
+  }
+
+  pub fn aws_secret(&'a self, account: usize) -> TransResult<&'a str> {
+    let config = self.config.aws.as_ref().ok_or(TransError::AwsConfig)?;
+    return account_value(account, Some(config.secret.expose()), config.accounts.as_slice(), Account::secret).ok_or(TransError::AwsConfig);
+  }
+
+  pub fn aws_token(&'a self, account: usize) -> Option<&'a str> {
+    let config = self.config.aws.as_ref()?;
+    return account_value(account, config.token.as_ref().map(Secret::expose), config.accounts.as_slice(), Account::token);
+  }
+
+  pub fn aws_accounts(&self) -> (usize, Rotation) {
+    return self.config.aws.as_ref().map(|c| (c.accounts.len() + 1, c.rotation)).unwrap_or((1, Rotation::Failover));
+  }
+
+  pub fn dict_enabled(&self) -> bool {
+    return self.config.dict.is_some() && self.engine_enabled("dict");
+  }
+
+  pub fn dict_path(&'a self) -> TransResult<&'a str> {
+    return self.config.dict.as_ref().map(|c| c.path.as_str()).ok_or(TransError::DictConfig);
+  }
+
+  pub fn dict_format(&'a self) -> Option<&'a str> {
+    return self.config.dict.as_ref().and_then(|c| c.format.as_deref());
+  }
+
+  pub fn custom_configs(&'a self) -> &'a [CustomConfig] {
+    return self.config.custom.as_slice();
+  }
+
+  pub fn command_configs(&'a self) -> &'a [CommandConfig] {
+    return self.config.command.as_slice();
+  }
+
+  pub fn config_ref(&self) -> &Config {
+    return &self.config;
+  }
+
+}
+
+// Credentials and anything else that must never show up in `Debug`/`Display`
+// output, the value is only reachable through `expose`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+  pub fn expose(&self) -> &str {
+    return self.0.as_str();
+  }
+}
+
+impl std::fmt::Debug for Secret {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    return f.write_str("Secret(***)");
+  }
+}
+
+impl std::fmt::Display for Secret {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    return f.write_str("***");
+  }
+}
+
+// `proxy`, `no_proxy`, `ca_cert` and `insecure_skip_verify`, accepted at the
+// top level and in every provider section, where they override the top level.
+#[derive(Debug, Default, Deserialize)]
+pub struct HttpConfig {
+  proxy: Option<Secret>,
+
+  no_proxy: Option<String>,
+
+  ca_cert: Option<String>,
+
+  insecure_skip_verify: Option<bool>,
+}
+
+impl HttpConfig {
+  fn is_empty(&self) -> bool {
+    return self.proxy.is_none() && self.no_proxy.is_none() && self.ca_cert.is_none() && self.insecure_skip_verify.is_none();
+  }
+
+  fn or(&self, global: &HttpConfig) -> HttpConfig {
+    return HttpConfig {
+      proxy: self.proxy.clone().or_else(|| global.proxy.clone()),
+      no_proxy: self.no_proxy.clone().or_else(|| global.no_proxy.clone()),
+      ca_cert: self.ca_cert.clone().or_else(|| global.ca_cert.clone()),
+      insecure_skip_verify: self.insecure_skip_verify.or(global.insecure_skip_verify),
+    };
+  }
+}
+
+// `no_proxy` is a comma separated list of hosts, each also matching its
+// subdomains, or `*` for every host.
+fn no_proxy_matches(no_proxy: &str, host: &str) -> bool {
+  return no_proxy.split(',')
+    .map(|entry| entry.trim().trim_start_matches('.'))
+    .filter(|entry| !entry.is_empty())
+    .any(|entry| entry == "*" || host == entry || host.ends_with(format!(".{}", entry).as_str()));
+}
+
+pub fn http_client(http: &HttpConfig, timeout: Option<u64>) -> TransResult<Client> {
+  let mut builder = Client::builder();
+  if let Some(timeout) = timeout {
+    builder = builder.timeout(std::time::Duration::from_secs(timeout));
+  }
+  if let Some(proxy) = http.proxy.as_ref() {
+    let proxy = Url::parse(proxy.expose()).map_err(|_| TransError::HttpConfig)?;
+    let no_proxy = http.no_proxy.clone().unwrap_or_default();
+    builder = builder.proxy(Proxy::custom(move |url| {
+      let host = url.host_str().unwrap_or_default();
+      if no_proxy_matches(no_proxy.as_str(), host) {
+        return None;
+      }
+      return Some(proxy.clone());
+    }));
+  }
+  if let Some(path) = http.ca_cert.as_ref() {
+    let pem = std::fs::read(path).map_err(|_| TransError::HttpConfig)?;
+    let certificate = Certificate::from_pem(pem.as_slice()).map_err(|_| TransError::HttpConfig)?;
+    builder = builder.add_root_certificate(certificate);
+  }
+  if http.insecure_skip_verify.unwrap_or(false) {
+    builder = builder.danger_accept_invalid_certs(true);
+  }
+  return builder.build().map_err(|_| TransError::HttpConfig);
+}
+
+// One more set of credentials for a provider, `accounts = [{ key = "..", secret = ".." }]`
+// in its section, the section's own credentials are account 0.
+#[derive(Debug, Deserialize)]
+pub struct Account {
+  key: Option<Secret>,
+
+  secret: Option<Secret>,
+
+  token: Option<Secret>,
+}
+
+impl Account {
+  fn key(&self) -> Option<&str> {
+    return self.key.as_ref().map(Secret::expose);
+  }
+
+  fn secret(&self) -> Option<&str> {
+    return self.secret.as_ref().map(Secret::expose);
+  }
+
+  fn token(&self) -> Option<&str> {
+    return self.token.as_ref().map(Secret::expose);
+  }
+}
+
+fn account_value<'a>(account: usize, own: Option<&'a str>, accounts: &'a [Account], field: fn(&'a Account) -> Option<&'a str>) -> Option<&'a str> {
+  return match account {
+    0 => own,
+    _ => accounts.get(account - 1).and_then(field),
+  };
+}
+
+// `failover` sticks to an account until its quota is exhausted, `round-robin`
+// moves on with every request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rotation {
+  #[default]
+  Failover,
+  RoundRobin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+  Text,
+  Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+  Auto,
+  Always,
+  Never,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
+  source: Option<String>,
+  target: Option<String>,
+  engines: Option<Vec<String>>,
+  format: Option<OutputFormat>,
+  timeout: Option<u64>,
+  color: Option<ColorChoice>,
+  domain: Option<String>,
+  #[serde(flatten)]
+  http: HttpConfig,
   baidu: Option<BaiduConfig>,
   aliyun: Option<AliYunConfig>,
   huoshan: Option<HuoShanConfig>,
-  tencent: Option<TencentConfig>
+  tencent: Option<TencentConfig>,
+  youdao: Option<YoudaoConfig>,
+  caiyun: Option<CaiYunConfig>,
+  niutrans: Option<NiuTransConfig>,
+  aws: Option<AwsConfig>,
+  dict: Option<DictConfig>,
+  #[serde(default)]
+  custom: Vec<CustomConfig>,
+  #[serde(default)]
+  command: Vec<CommandConfig>
 }
 
 #[derive(Debug, Deserialize)]
 struct BaiduConfig {
   key: String,
 
-  secret: String,
+  secret: Secret,
+
+  domain: Option<String>,
+
+  #[serde(default)]
+  accounts: Vec<Account>,
+
+  #[serde(default)]
+  rotation: Rotation,
+
+  #[serde(flatten)]
+  http: HttpConfig,
 }
 
 #[derive(Debug, Deserialize)]
 struct AliYunConfig {
   key: String,
 
-  secret: String,
+  secret: Secret,
+
+  token: Option<Secret>,
+
+  api: Option<String>,
+
+  action: Option<String>,
+
+  scene: Option<String>,
+
+  format: Option<String>,
+
+  #[serde(default)]
+  accounts: Vec<Account>,
+
+  #[serde(default)]
+  rotation: Rotation,
+
+  #[serde(flatten)]
+  http: HttpConfig,
 }
 
 #[derive(Debug, Deserialize)]
 struct HuoShanConfig {
   key: String,
 
-  secret: String,
+  secret: Secret,
+
+  token: Option<Secret>,
+
+  #[serde(default)]
+  accounts: Vec<Account>,
+
+  #[serde(default)]
+  rotation: Rotation,
+
+  #[serde(flatten)]
+  http: HttpConfig,
 }
 
 #[derive(Debug, Deserialize)]
 struct TencentConfig {
   key: String,
 
-  secret: String,
+  secret: Secret,
+
+  project_id: Option<i64>,
+
+  token: Option<Secret>,
+
+  #[serde(default)]
+  accounts: Vec<Account>,
+
+  #[serde(default)]
+  rotation: Rotation,
+
+  #[serde(flatten)]
+  http: HttpConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct YoudaoConfig {
+  key: String,
+
+  secret: Secret,
+
+  domain: Option<String>,
+
+  #[serde(default)]
+  accounts: Vec<Account>,
+
+  #[serde(default)]
+  rotation: Rotation,
+
+  #[serde(flatten)]
+  http: HttpConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct CaiYunConfig {
+  token: Secret,
+
+  #[serde(default)]
+  accounts: Vec<Account>,
+
+  #[serde(default)]
+  rotation: Rotation,
+
+  #[serde(flatten)]
+  http: HttpConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct NiuTransConfig {
+  key: Secret,
+
+  #[serde(default)]
+  accounts: Vec<Account>,
+
+  #[serde(default)]
+  rotation: Rotation,
+
+  #[serde(flatten)]
+  http: HttpConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct AwsConfig {
+  key: String,
+
+  secret: Secret,
+
+  token: Option<Secret>,
+
+  region: Option<String>,
+
+  #[serde(default)]
+  accounts: Vec<Account>,
+
+  #[serde(default)]
+  rotation: Rotation,
+
+  #[serde(flatten)]
+  http: HttpConfig,
+}
+
+impl<'a> Config {
+  pub fn source(&'a self) -> Option<&'a str> {
+    return self.source.as_deref();
+  }
+
+  pub fn target(&'a self) -> Option<&'a str> {
+    return self.target.as_deref();
+  }
+
+  pub fn format(&self) -> OutputFormat {
+    return self.format.unwrap_or(OutputFormat::Text);
+  }
+
+  // seconds, no limit unless configured
+  pub fn timeout(&self) -> Option<u64> {
+    return self.timeout;
+  }
+
+  pub fn color(&self) -> ColorChoice {
+    return self.color.unwrap_or(ColorChoice::Auto);
+  }
+
+  // custom engines are prefixed to keep them apart from the built-in ones
+  fn engine_http(&'a self) -> Vec<(String, &'a HttpConfig)> {
+    let sections = [
+      ("baidu", self.baidu.as_ref().map(|c| &c.http)),
+      ("aliyun", self.aliyun.as_ref().map(|c| &c.http)),
+      ("huoshan", self.huoshan.as_ref().map(|c| &c.http)),
+      ("tencent", self.tencent.as_ref().map(|c| &c.http)),
+      ("youdao", self.youdao.as_ref().map(|c| &c.http)),
+      ("caiyun", self.caiyun.as_ref().map(|c| &c.http)),
+      ("niutrans", self.niutrans.as_ref().map(|c| &c.http)),
+      ("aws", self.aws.as_ref().map(|c| &c.http)),
+    ];
+    let mut engines: Vec<(String, &HttpConfig)> = sections.into_iter()
+      .filter_map(|(engine, http)| http.map(|http| (String::from(engine), http)))
+      .collect();
+    for custom in self.custom.iter() {
+      engines.push((format!("custom.{}", custom.name), &custom.http));
+    }
+    return engines;
+  }
+}
+
+#[derive(Debug, Deserialize)]
+struct DictConfig {
+  path: String,
+
+  format: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CustomConfig {
+  name: String,
+
+  #[serde(default = "default_custom_method")]
+  method: String,
+
+  // may carry an API key in its query
+  url: Secret,
+
+  #[serde(default)]
+  headers: HashMap<String, Secret>,
+
+  #[serde(default)]
+  query: HashMap<String, Secret>,
+
+  #[serde(default)]
+  form: HashMap<String, Secret>,
+
+  body: Option<String>,
+
+  result: String,
+
+  #[serde(flatten)]
+  http: HttpConfig,
+}
+
+fn default_custom_method() -> String {
+  return String::from("POST");
+}
+
+impl<'a> CustomConfig {
+  pub fn name(&'a self) -> &'a str {
+    return self.name.as_str();
+  }
+
+  pub fn method(&'a self) -> &'a str {
+    return self.method.as_str();
+  }
+
+  pub fn url(&'a self) -> &'a str {
+    return self.url.expose();
+  }
+
+  pub fn headers(&'a self) -> &'a HashMap<String, Secret> {
+    return &self.headers;
+  }
+
+  pub fn query(&'a self) -> &'a HashMap<String, Secret> {
+    return &self.query;
+  }
+
+  pub fn form(&'a self) -> &'a HashMap<String, Secret> {
+    return &self.form;
+  }
+
+  pub fn body(&'a self) -> Option<&'a str> {
+    return self.body.as_deref();
+  }
+
+  pub fn result(&'a self) -> &'a str {
+    return self.result.as_str();
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommandConfig {
+  name: String,
+
+  program: String,
+
+  // may carry a token as a flag
+  #[serde(default)]
+  args: Vec<Secret>,
+
+  timeout: Option<u64>,
+}
+
+const DEFAULT_COMMAND_TIMEOUT: u64 = 10;
+
+impl<'a> CommandConfig {
+  pub fn name(&'a self) -> &'a str {
+    return self.name.as_str();
+  }
+
+  pub fn program(&'a self) -> &'a str {
+    return self.program.as_str();
+  }
+
+  pub fn args(&'a self) -> impl Iterator<Item=&'a str> {
+    return self.args.iter().map(Secret::expose);
+  }
+
+  // falls back to the global `timeout`, then to ten seconds
+  pub fn timeout(&self, default: Option<u64>) -> u64 {
+    return self.timeout.or(default).unwrap_or(DEFAULT_COMMAND_TIMEOUT);
+  }
 }
 
 pub fn md5_str(str: &str) -> Vec<u8> {
@@ -226,6 +1235,18 @@ pub fn sha1_hmac(key: &[u8], data: &[u8]) -> TransResult<Vec<u8>> {
   return Ok(hmac.finalize().into_bytes().to_vec());
 }
 
+// RFC 3986 encoding, only unreserved characters are kept as is
+pub fn percent_encode(str: &str) -> String {
+  let mut encoded = String::with_capacity(str.len());
+  for byte in str.bytes() {
+    match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+      _ => encoded.push_str(format!("%{:02X}", byte).as_str()),
+    }
+  }
+  return encoded;
+}
+
 pub fn hex_byte(data: &[u8]) -> String {
   return hex::encode(data);
 }
@@ -233,3 +1254,173 @@ pub fn hex_byte(data: &[u8]) -> String {
 pub fn base64_byte(data: &[u8]) -> String {
   return base64::encode(data);
 }
+
+// Writes a temp file next to `path`, flushes it to disk and renames it over `path`,
+// so an interrupted write leaves the old file intact. `permissions` are set before
+// any content is written.
+pub fn write_atomic(path: &std::path::Path, content: &[u8], permissions: Option<std::fs::Permissions>) -> std::io::Result<()> {
+  use std::io::Write;
+  let name = path.file_name().ok_or(std::io::ErrorKind::InvalidInput)?.to_string_lossy();
+  let temp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+  let written = std::fs::OpenOptions::new().write(true).create_new(true).open(temp.as_path())
+    .and_then(|mut file| {
+      if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+      }
+      file.write_all(content)?;
+      return file.sync_all();
+    })
+    .and_then(|_| std::fs::rename(temp.as_path(), path));
+  if written.is_err() {
+    let _ = std::fs::remove_file(temp.as_path());
+  }
+  return written;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn config_debug_is_redacted() {
+    let config: Config = toml::from_str(r#"
+      [baidu]
+      key = "appid"
+      secret = "baidu-secret"
+
+      [tencent]
+      key = "AKID"
+      secret = "tencent-secret"
+      token = "tencent-token"
+
+      [[custom]]
+      name = "custom"
+      url = "https://example.com/translate?key=url-secret"
+      headers = { Authorization = "custom-secret" }
+      result = "/text"
+
+      [[command]]
+      name = "command"
+      program = "translate"
+      args = ["--token", "command-secret"]
+    "#).unwrap();
+    let dump = format!("{:?}", config);
+    for secret in ["baidu-secret", "tencent-secret", "tencent-token", "custom-secret", "url-secret", "command-secret"] {
+      assert!(!dump.contains(secret), "{} leaked", secret);
+    }
+    assert_eq!(Secret(String::from("secret")).to_string(), "***");
+  }
+
+  // codes taken from the error documentation of each provider
+  #[test]
+  fn provider_errors_are_classified() {
+    let cases = [
+      ("AuthFailure.SignatureExpire", "", TransError::ClockSkew),
+      ("AuthFailure.SignatureFailure", "", TransError::AuthError),
+      ("FailedOperation.NoFreeAmount", "", TransError::QuotaExceeded),
+      ("UnsupportedRegion", "", TransError::UnsupportedRegion),
+      ("InvalidTimeStamp.Expired", "", TransError::ClockSkew),
+      ("InvalidAccessKeyId.NotFound", "", TransError::AuthError),
+      ("Throttling.User", "", TransError::RateLimited),
+      ("RequestLimitExceeded", "", TransError::RateLimited),
+      ("ThrottlingException", "Rate exceeded", TransError::RateLimited),
+      ("SignatureDoesNotMatch", "", TransError::AuthError),
+      ("InvalidSignatureException", "Signature expired: 20220101T000000Z is now earlier than ..", TransError::ClockSkew),
+      ("UnrecognizedClientException", "The security token included in the request is invalid.", TransError::AuthError),
+      ("InternalServerException", "", TransError::ResponseError),
+    ];
+    for (code, message, expected) in cases {
+      let error = classify_error(StatusCode::BAD_REQUEST, code, message);
+      assert_eq!(format!("{:?}", error), format!("{:?}", expected), "{}", code);
+    }
+    assert!(matches!(classify_error(StatusCode::FORBIDDEN, "", ""), TransError::AuthError));
+    assert!(matches!(classify_error(StatusCode::TOO_MANY_REQUESTS, "", ""), TransError::RateLimited));
+  }
+
+  #[test]
+  fn no_proxy_hosts_match_subdomains() {
+    assert!(no_proxy_matches("localhost, .example.com", "api.example.com"));
+    assert!(no_proxy_matches("example.com", "example.com"));
+    assert!(no_proxy_matches("*", "tmt.tencentcloudapi.com"));
+    assert!(!no_proxy_matches("example.com", "badexample.com"));
+    assert!(!no_proxy_matches("", "example.com"));
+  }
+
+  #[tokio::test]
+  async fn accounts_rotate() {
+    let config: Config = toml::from_str(r#"
+      [baidu]
+      key = "first"
+      secret = "first-secret"
+      accounts = [{ key = "second", secret = "second-secret" }, { key = "third", secret = "third-secret" }]
+
+      [tencent]
+      key = "first"
+      secret = "first-secret"
+      rotation = "round-robin"
+      accounts = [{ key = "second", secret = "second-secret" }]
+    "#).unwrap();
+    let context = Context::create(TransReq::create("hello", "en", "zh"), Client::new(), config);
+    assert_eq!(context.baidu_key(2).unwrap(), "third");
+    assert_eq!(context.baidu_secret(1).unwrap(), "second-secret");
+    let robin: Vec<usize> = (0..3).map(|_| context.next_account("tencent", context.tencent_accounts())).collect();
+    assert_eq!(robin, vec![0, 1, 0]);
+
+    // the first account is out of quota, it's skipped from then on
+    let send = |account: usize| async move {
+      return match account {
+        0 => Err(TransError::QuotaExceeded),
+        _ => Ok(TransRes::create(account.to_string())),
+      };
+    };
+    let res = with_account(&context, "baidu", context.baidu_accounts(), send).await.unwrap();
+    assert_eq!(res.result(), "1");
+    assert_eq!(context.next_account("baidu", context.baidu_accounts()), 1);
+
+    // a rate limit keeps the account
+    let send = |_| async move { Err::<TransRes, _>(TransError::RateLimited) };
+    assert!(matches!(with_account(&context, "baidu", context.baidu_accounts(), send).await, Err(TransError::RateLimited)));
+    assert_eq!(context.next_account("baidu", context.baidu_accounts()), 1);
+  }
+
+  #[tokio::test]
+  async fn clock_skew_is_corrected_once() {
+    use chrono::TimeZone;
+    let local = Utc.timestamp_opt(1664611200, 0).unwrap();
+    let config: Config = toml::from_str("").unwrap();
+    let context = Context::create(TransReq::create("hello", "en", "zh"), Client::new(), config)
+      .with_clock(FixedClock::create(local, "1"));
+    let server = local + Duration::seconds(600);
+    let attempts = Mutex::new(Vec::new());
+    let res = retry_on_skew(&context, || async {
+      attempts.lock().unwrap().push(context.now());
+      if context.now() != server {
+        return Err(context.correct_clock(TransError::ClockSkew, Some(server)));
+      }
+      return Ok(TransRes::create(String::from("ok")));
+    }).await;
+    assert!(res.is_ok());
+    assert_eq!(*attempts.lock().unwrap(), vec![local, server]);
+    assert_eq!(context.clock_skew(), Some(Duration::seconds(600)));
+  }
+
+  #[test]
+  fn atomic_writes_replace_the_file() {
+    let dir = std::env::temp_dir().join(format!("translator-write-{}", std::process::id()));
+    std::fs::create_dir_all(dir.as_path()).unwrap();
+    let path = dir.join("out.txt");
+    std::fs::write(path.as_path(), "old").unwrap();
+    write_atomic(path.as_path(), b"new", None).unwrap();
+    assert_eq!(std::fs::read_to_string(path.as_path()).unwrap(), "new");
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      write_atomic(path.as_path(), b"secret", Some(std::fs::Permissions::from_mode(0o600))).unwrap();
+      assert_eq!(std::fs::metadata(path.as_path()).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    // no temp file is left behind
+    assert_eq!(std::fs::read_dir(dir.as_path()).unwrap().count(), 1);
+    assert!(write_atomic(dir.join("missing").join("out.txt").as_path(), b"new", None).is_err());
+    let _ = std::fs::remove_dir_all(dir);
+  }
+}
@@ -0,0 +1,274 @@
+use std::path::{Path, PathBuf};
+use crate::common::{Config, TransError, TransResult};
+use crate::credential;
+
+const CONFIG_FILE: &str = "translator.toml";
+const CONFIG_ENV: &str = "TRANSLATOR_CONFIG";
+const PROFILE_ENV: &str = "TRANSLATOR_PROFILE";
+
+const ENV_PROVIDERS: [&str; 9] = ["baidu", "aliyun", "huoshan", "tencent", "youdao", "caiyun", "niutrans", "aws", "dict"];
+
+// A place a config file is looked for, `required` ones must exist.
+pub struct ConfigSource {
+  origin: &'static str,
+  path: PathBuf,
+  required: bool,
+}
+
+impl<'a> ConfigSource {
+  fn create(origin: &'static str, path: PathBuf, required: bool) -> Self {
+    return ConfigSource {
+      origin,
+      path,
+      required,
+    };
+  }
+
+  pub fn origin(&'a self) -> &'a str {
+    return self.origin;
+  }
+
+  pub fn path(&'a self) -> &'a Path {
+    return self.path.as_path();
+  }
+
+  pub fn exists(&self) -> bool {
+    return self.path.is_file();
+  }
+}
+
+fn project_path() -> Option<PathBuf> {
+  let current_dir = std::env::current_dir().ok()?;
+  return current_dir.ancestors()
+    .map(|dir| dir.join(CONFIG_FILE))
+    .find(|path| path.is_file());
+}
+
+// Highest precedence first: `--config`, `$TRANSLATOR_CONFIG`, the closest
+// `translator.toml` in the current directory or its parents, the user config
+// and the system config.
+pub fn sources(explicit: Option<&Path>) -> Vec<ConfigSource> {
+  let mut sources = Vec::with_capacity(6);
+  if let Some(path) = explicit {
+    sources.push(ConfigSource::create("--config", PathBuf::from(path), true));
+  }
+  if let Some(path) = std::env::var_os(CONFIG_ENV).filter(|path| !path.is_empty()) {
+    sources.push(ConfigSource::create(CONFIG_ENV, PathBuf::from(path), true));
+  }
+  sources.push(ConfigSource::create("project", project_path().unwrap_or_else(|| PathBuf::from(CONFIG_FILE)), false));
+  if let Some(config_dir) = dirs::config_dir() {
+    sources.push(ConfigSource::create("user", config_dir.join("translator").join(CONFIG_FILE), false));
+    sources.push(ConfigSource::create("user", config_dir.join(CONFIG_FILE), false));
+  }
+  #[cfg(unix)]
+  sources.push(ConfigSource::create("system", PathBuf::from("/etc/translator").join(CONFIG_FILE), false));
+  return sources;
+}
+
+async fn parse_config_from_file<P: AsRef<Path>>(path: P) -> TransResult<toml::Value> {
+  let content = tokio::fs::read(path)
+    .await.map_err(|_| TransError::ParseConfig)?;
+  return toml::from_slice(content.as_slice()).map_err(|_| TransError::ParseConfig);
+}
+
+// tables are merged key by key, anything else in `upper` replaces `lower`
+fn merge(lower: &mut toml::Value, upper: toml::Value) {
+  match (lower, upper) {
+    (toml::Value::Table(lower), toml::Value::Table(upper)) => {
+      for (key, value) in upper {
+        match lower.get_mut(key.as_str()) {
+          Some(existing) => merge(existing, value),
+          None => {
+            lower.insert(key, value);
+          }
+        }
+      }
+    }
+    (lower, upper) => *lower = upper,
+  }
+}
+
+// merges every existing source, returns the merged value and the files it came from
+pub async fn load(explicit: Option<&Path>) -> TransResult<(toml::Value, Vec<PathBuf>)> {
+  let mut value = toml::Value::Table(toml::value::Table::new());
+  let mut loaded = Vec::new();
+  for source in sources(explicit).into_iter().rev() {
+    if !source.exists() {
+      if source.required {
+        return Err(TransError::ParseConfig);
+      }
+      continue;
+    }
+    if loaded.contains(&source.path) {
+      continue;
+    }
+    merge(&mut value, parse_config_from_file(source.path()).await?);
+    loaded.push(source.path);
+  }
+  loaded.reverse();
+  return Ok((value, loaded));
+}
+
+// `[profile.<name>]` tables are laid over the rest of the config, the profile
+// comes from `--profile` or `$TRANSLATOR_PROFILE`.
+fn apply_profile(value: &mut toml::Value, profile: Option<&str>) -> TransResult<()> {
+  let profiles = value.as_table_mut().and_then(|table| table.remove("profile"));
+  let name = match profile.map(String::from).or_else(|| std::env::var(PROFILE_ENV).ok()) {
+    Some(name) if !name.is_empty() => name,
+    _ => return Ok(()),
+  };
+  let profile = profiles.as_ref()
+    .and_then(|profiles| profiles.get(name.as_str()))
+    .filter(|profile| profile.is_table())
+    .ok_or(TransError::ProfileNotFound)?;
+  merge(value, profile.clone());
+  return Ok(());
+}
+
+// `overrides` holds the command line flags, they win over every file and profile
+pub async fn parse_config(explicit: Option<&Path>, profile: Option<&str>, overrides: toml::value::Table) -> TransResult<Config> {
+  let (value, _) = load(explicit).await?;
+  let mut value = layer(value, profile, std::env::vars(), overrides)?;
+  credential::resolve(&mut value, chrono::Utc::now()).await?;
+  return value.try_into().map_err(|_| TransError::ParseConfig);
+}
+
+// files < profile < environment < flags, flags alone don't make a config
+fn layer<I>(mut value: toml::Value, profile: Option<&str>, vars: I, overrides: toml::value::Table) -> TransResult<toml::Value>
+  where
+    I: Iterator<Item=(String, String)>,
+{
+  apply_profile(&mut value, profile)?;
+  let from_env = config_from_vars(&mut value, vars);
+  if value.as_table().map(|table| table.is_empty()).unwrap_or(true) && !from_env {
+    return Err(TransError::ParseConfig);
+  }
+  merge(&mut value, toml::Value::Table(overrides));
+  return Ok(value);
+}
+
+// `TRANSLATOR_BAIDU_KEY=..` becomes `key = ".."` in the `[baidu]` section,
+// returns whether any variable was applied.
+fn config_from_vars<I>(value: &mut toml::Value, vars: I) -> bool
+  where
+    I: Iterator<Item=(String, String)>,
+{
+  let mut applied = false;
+  let table = match value.as_table_mut() {
+    Some(table) => table,
+    None => return false,
+  };
+  for (name, env_value) in vars {
+    let name = match name.strip_prefix("TRANSLATOR_") {
+      Some(name) => name.to_lowercase(),
+      None => continue,
+    };
+    for provider in ENV_PROVIDERS {
+      let field = match name.strip_prefix(provider).and_then(|field| field.strip_prefix('_')) {
+        Some(field) if !field.is_empty() => field,
+        _ => continue,
+      };
+      let section = table.entry(String::from(provider))
+        .or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+      if let Some(section) = section.as_table_mut() {
+        section.insert(String::from(field), env_toml(field, env_value.as_str()));
+        applied = true;
+      }
+    }
+  }
+  return applied;
+}
+
+// `5`, `true` or `["a", "b"]` keep their TOML type, anything else is a string, credentials
+// and commands always are, a Baidu APP ID is all digits
+fn env_toml(field: &str, env_value: &str) -> toml::Value {
+  let string = toml::Value::String(String::from(env_value));
+  if ["key", "secret", "token"].contains(&field) || field.ends_with("_command") {
+    return string;
+  }
+  return format!("value = {}", env_value).parse::<toml::Value>().ok()
+    .and_then(|parsed| parsed.get("value").cloned())
+    .unwrap_or(string);
+}
+
+// `translator config path`
+pub async fn print_path(explicit: Option<&Path>) -> TransResult<()> {
+  let loaded = load(explicit).await;
+  for source in sources(explicit) {
+    let state = if source.exists() {
+      console::style("loaded").green()
+    } else if source.required {
+      console::style("missing").red()
+    } else {
+      console::style("missing").dim()
+    };
+    println!("{:>8} {:>18}  {}", state, source.origin(), source.path().display());
+  }
+  return loaded.map(|_| ());
+}
+
+#[cfg(test)]
+mod tests {
+  use reqwest::Client;
+  use crate::common::{Config, Context, TransReq};
+  use super::*;
+
+  fn vars(vars: &[(&str, &str)]) -> std::vec::IntoIter<(String, String)> {
+    return vars.iter().map(|(name, value)| (String::from(*name), String::from(*value))).collect::<Vec<_>>().into_iter();
+  }
+
+  #[test]
+  fn layers_apply_in_order() {
+    let file: toml::Value = toml::from_str("[tencent]\nproject_id = 1\nregion = \"file\"\n[profile.work.tencent]\nproject_id = 2").unwrap();
+    let mut flags = toml::value::Table::new();
+    flags.insert(String::from("tencent"), toml::from_str("project_id = 4").unwrap());
+    let env = [("TRANSLATOR_TENCENT_PROJECT_ID", "3")];
+
+    let value = layer(file.clone(), Some("work"), vars(&env), flags.clone()).unwrap();
+    assert_eq!(value["tencent"]["project_id"], toml::Value::Integer(4));
+    assert_eq!(value["tencent"]["region"], toml::Value::String(String::from("file")));
+    let value = layer(file.clone(), Some("work"), vars(&env), toml::value::Table::new()).unwrap();
+    assert_eq!(value["tencent"]["project_id"], toml::Value::Integer(3));
+    let value = layer(file.clone(), Some("work"), vars(&[]), toml::value::Table::new()).unwrap();
+    assert_eq!(value["tencent"]["project_id"], toml::Value::Integer(2));
+    let value = layer(file.clone(), None, vars(&[]), toml::value::Table::new()).unwrap();
+    assert_eq!(value["tencent"]["project_id"], toml::Value::Integer(1));
+  }
+
+  // `translator hello -t en` without any config file is still an error
+  #[test]
+  fn flags_alone_are_no_config() {
+    let mut flags = toml::value::Table::new();
+    flags.insert(String::from("target"), toml::Value::String(String::from("en")));
+    let empty = toml::Value::Table(toml::value::Table::new());
+    assert!(matches!(layer(empty.clone(), None, vars(&[]), flags.clone()), Err(TransError::ParseConfig)));
+    let value = layer(empty, None, vars(&[("TRANSLATOR_CAIYUN_TOKEN", "token")]), flags).unwrap();
+    assert_eq!(value["target"], toml::Value::String(String::from("en")));
+  }
+
+  #[test]
+  fn env_values_keep_their_type() {
+    let env = [
+      ("TRANSLATOR_TENCENT_PROJECT_ID", "5"),
+      ("TRANSLATOR_TENCENT_KEY", "2015063000000001"),
+      ("TRANSLATOR_TENCENT_SECRET", "secret"),
+      ("TRANSLATOR_AWS_KEY", "AKID"),
+      ("TRANSLATOR_AWS_SECRET", "secret"),
+      ("TRANSLATOR_AWS_REGION", "eu-west-1"),
+      ("TRANSLATOR_AWS_INSECURE_SKIP_VERIFY", "true"),
+      ("HOME", "/root"),
+    ];
+    let mut value = toml::Value::Table(toml::value::Table::new());
+    assert!(config_from_vars(&mut value, vars(&env)));
+    assert_eq!(value["tencent"]["project_id"], toml::Value::Integer(5));
+    assert_eq!(value["tencent"]["key"], toml::Value::String(String::from("2015063000000001")));
+    assert_eq!(value["aws"]["region"], toml::Value::String(String::from("eu-west-1")));
+    assert_eq!(value["aws"]["insecure_skip_verify"], toml::Value::Boolean(true));
+
+    let config: Config = value.try_into().unwrap();
+    let context = Context::create(TransReq::create("apple", "auto", "zh"), Client::new(), config);
+    assert_eq!(context.tencent_project_id(), 5);
+    assert_eq!(context.tencent_key(0).unwrap(), "2015063000000001");
+    assert_eq!(context.aws_region(), "eu-west-1");
+  }
+}
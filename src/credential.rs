@@ -0,0 +1,275 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use tokio::process::Command;
+use crate::common::{Secret, TransError, TransResult, hex_byte, md5_str};
+use crate::secrets::SecretStore;
+
+// providers whose section may carry a `credential_command`
+const PROVIDERS: [&str; 4] = ["tencent", "aliyun", "huoshan", "aws"];
+
+// credential fields each provider can't work without
+const REQUIRED_FIELDS: [(&str, &[&str]); 8] = [
+  ("baidu", &["key", "secret"]),
+  ("aliyun", &["key", "secret"]),
+  ("huoshan", &["key", "secret"]),
+  ("tencent", &["key", "secret"]),
+  ("youdao", &["key", "secret"]),
+  ("caiyun", &["token"]),
+  ("niutrans", &["key"]),
+  ("aws", &["key", "secret"]),
+];
+
+const SECRET_FIELDS: [&str; 3] = ["key", "secret", "token"];
+
+// refresh cached credentials this long before they expire
+const EXPIRY_MARGIN: i64 = 300;
+
+// Same shape as the output of an AWS `credential_process`, which most STS helpers can produce.
+#[derive(Serialize, Deserialize, Debug)]
+struct Credential {
+  #[serde(rename = "AccessKeyId")]
+  key: String,
+
+  #[serde(rename = "SecretAccessKey")]
+  secret: Secret,
+
+  #[serde(rename = "SessionToken")]
+  token: Option<Secret>,
+
+  #[serde(rename = "Expiration")]
+  expiration: Option<String>,
+}
+
+impl Credential {
+  fn valid_at(&self, now: DateTime<Utc>) -> bool {
+    return self.expiration.as_ref()
+      .and_then(|expiration| DateTime::parse_from_rfc3339(expiration).ok())
+      .map(|expiration| expiration.timestamp() - EXPIRY_MARGIN > now.timestamp())
+      .unwrap_or(false);
+  }
+}
+
+// keyed on the command too, a different profile or command never gets another account's keys
+fn cache_file(dir: &Path, provider: &str, command: &str) -> PathBuf {
+  let hash = hex_byte(md5_str(command).as_slice());
+  return dir.join(format!("{}-{}-credential.json", provider, hash));
+}
+
+fn cache_path(provider: &str, command: &str) -> Option<PathBuf> {
+  return dirs::cache_dir().map(|path| cache_file(path.join("translator").as_path(), provider, command));
+}
+
+async fn read_cache(path: &Path, now: DateTime<Utc>) -> Option<Credential> {
+  let content = tokio::fs::read(path).await.ok()?;
+  let credential: Credential = serde_json::from_slice(content.as_slice()).ok()?;
+  return if credential.valid_at(now) { Some(credential) } else { None };
+}
+
+async fn write_cache(path: &Path, credential: &Credential) {
+  let content = match serde_json::to_vec(credential) {
+    Ok(content) => content,
+    Err(_) => return,
+  };
+  if let Some(parent) = path.parent() {
+    let _ = tokio::fs::create_dir_all(parent).await;
+  }
+  let mut options = tokio::fs::OpenOptions::new();
+  options.write(true).create(true).truncate(true);
+  #[cfg(unix)]
+  options.mode(0o600);
+  if let Ok(mut file) = options.open(path).await {
+    use tokio::io::AsyncWriteExt;
+    let _ = file.write_all(content.as_slice()).await;
+  }
+}
+
+pub async fn run_command(command: &str) -> TransResult<Vec<u8>> {
+  #[cfg(windows)]
+  let mut process = Command::new("cmd");
+  #[cfg(windows)]
+  process.arg("/C").arg(command);
+  #[cfg(not(windows))]
+  let mut process = Command::new("sh");
+  #[cfg(not(windows))]
+  process.arg("-c").arg(command);
+  process.kill_on_drop(true);
+  let output = tokio::time::timeout(Duration::from_secs(30), process.output())
+    .await.map_err(|_| TransError::CommandTimeout)?
+    .map_err(|_| TransError::CommandError)?;
+  if !output.status.success() {
+    return Err(TransError::CommandError);
+  }
+  return Ok(output.stdout);
+}
+
+async fn fetch(provider: &str, command: &str, now: DateTime<Utc>) -> TransResult<Credential> {
+  let path = cache_path(provider, command);
+  if let Some(path) = path.as_ref() {
+    if let Some(credential) = read_cache(path.as_path(), now).await {
+      return Ok(credential);
+    }
+  }
+  let output = run_command(command).await?;
+  let credential: Credential = serde_json::from_slice(output.as_slice())
+    .map_err(|_| TransError::CredentialError)?;
+  if let Some(path) = path.as_ref().filter(|_| credential.valid_at(now)) {
+    write_cache(path.as_path(), &credential).await;
+  }
+  return Ok(credential);
+}
+
+// Fills the credentials missing from the provider sections, first from the
+// `key_command`/`secret_command`/`token_command` fields, then from the
+// `credential_command` and finally from the secret store.
+pub async fn resolve(config: &mut toml::Value, now: DateTime<Utc>) -> TransResult<()> {
+  resolve_field_commands(config).await?;
+  resolve_credential_commands(config, now).await?;
+  return resolve_store(config);
+}
+
+// like `Context::engine_enabled`, sections left out by `engines` aren't resolved,
+// their commands never run and the secret store isn't opened for them
+fn selected<I>(config: &toml::Value, providers: I) -> Vec<&'static str>
+  where
+    I: Iterator<Item=&'static str>,
+{
+  let engines = config.get("engines").and_then(|engines| engines.as_array());
+  return providers
+    .filter(|provider| engines.map(|engines| engines.iter()
+      .filter_map(|engine| engine.as_str())
+      .any(|engine| engine.eq_ignore_ascii_case(provider))
+    ).unwrap_or(true))
+    .collect();
+}
+
+async fn resolve_field_commands(config: &mut toml::Value) -> TransResult<()> {
+  for provider in selected(config, REQUIRED_FIELDS.iter().map(|(provider, _)| *provider)) {
+    let section = match config.get_mut(provider).and_then(|section| section.as_table_mut()) {
+      Some(section) => section,
+      None => continue,
+    };
+    for field in SECRET_FIELDS {
+      if section.contains_key(field) {
+        continue;
+      }
+      let command = match section.get(format!("{}_command", field).as_str()).and_then(|command| command.as_str()) {
+        Some(command) => String::from(command),
+        None => continue,
+      };
+      let output = run_command(command.as_str()).await?;
+      let value = String::from_utf8(output).map_err(|_| TransError::CredentialError)?;
+      section.insert(String::from(field), toml::Value::String(String::from(value.trim())));
+    }
+  }
+  return Ok(());
+}
+
+fn resolve_store(config: &mut toml::Value) -> TransResult<()> {
+  let providers = selected(config, REQUIRED_FIELDS.iter().map(|(provider, _)| *provider));
+  let incomplete = REQUIRED_FIELDS.iter().filter(|(provider, _)| providers.contains(provider)).any(|(provider, fields)| {
+    return config.get(provider)
+      .and_then(|section| section.as_table())
+      .map(|section| fields.iter().any(|field| !section.contains_key(*field)))
+      .unwrap_or(false);
+  });
+  if !incomplete || !SecretStore::exists() {
+    return Ok(());
+  }
+  let store = SecretStore::open()?;
+  for provider in selected(config, REQUIRED_FIELDS.iter().map(|(provider, _)| *provider)) {
+    let section = match config.get_mut(provider).and_then(|section| section.as_table_mut()) {
+      Some(section) => section,
+      None => continue,
+    };
+    for field in SECRET_FIELDS {
+      if section.contains_key(field) {
+        continue;
+      }
+      if let Some(value) = store.get(format!("{}.{}", provider, field).as_str()) {
+        section.insert(String::from(field), toml::Value::String(String::from(value)));
+      }
+    }
+  }
+  return Ok(());
+}
+
+// Runs the `credential_command` of every provider section that has one and
+// writes the temporary `key`, `secret` and `token` into that section.
+async fn resolve_credential_commands(config: &mut toml::Value, now: DateTime<Utc>) -> TransResult<()> {
+  for provider in selected(config, PROVIDERS.into_iter()) {
+    let section = match config.get_mut(provider).and_then(|section| section.as_table_mut()) {
+      Some(section) => section,
+      None => continue,
+    };
+    let command = match section.get("credential_command").and_then(|command| command.as_str()) {
+      Some(command) => String::from(command),
+      None => continue,
+    };
+    let credential = fetch(provider, command.as_str(), now).await?;
+    section.insert(String::from("key"), toml::Value::String(credential.key));
+    section.insert(String::from("secret"), toml::Value::String(String::from(credential.secret.expose())));
+    if let Some(token) = credential.token {
+      section.insert(String::from("token"), toml::Value::String(String::from(token.expose())));
+    }
+  }
+  return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+  use super::*;
+
+  fn credential(expiration: Option<&str>) -> Credential {
+    let content = serde_json::json!({
+      "AccessKeyId": "AKID",
+      "SecretAccessKey": "secret",
+      "SessionToken": "token",
+      "Expiration": expiration,
+    });
+    return serde_json::from_value(content).unwrap();
+  }
+
+  #[test]
+  fn credentials_expire_early() {
+    let now = Utc.timestamp_opt(1664611200, 0).unwrap();
+    assert!(credential(Some("2022-10-01T09:00:00Z")).valid_at(now));
+    // inside the five minute margin
+    assert!(!credential(Some("2022-10-01T08:04:00Z")).valid_at(now));
+    assert!(!credential(Some("2022-10-01T07:00:00Z")).valid_at(now));
+    assert!(!credential(Some("tomorrow")).valid_at(now));
+    assert!(!credential(None).valid_at(now));
+  }
+
+  #[tokio::test]
+  async fn cache_is_keyed_on_the_command() {
+    let dir = std::env::temp_dir().join(format!("translator-credential-{}", std::process::id()));
+    let now = Utc.timestamp_opt(1664611200, 0).unwrap();
+    let path = cache_file(dir.as_path(), "tencent", "sts work");
+    assert_ne!(path, cache_file(dir.as_path(), "tencent", "sts personal"));
+    assert_ne!(path, cache_file(dir.as_path(), "aliyun", "sts work"));
+
+    assert!(read_cache(path.as_path(), now).await.is_none());
+    write_cache(path.as_path(), &credential(Some("2022-10-01T09:00:00Z"))).await;
+    let cached = read_cache(path.as_path(), now).await.unwrap();
+    assert_eq!(cached.key, "AKID");
+    assert!(read_cache(cache_file(dir.as_path(), "tencent", "sts personal").as_path(), now).await.is_none());
+    // expired entries are misses
+    assert!(read_cache(path.as_path(), Utc.timestamp_opt(1664614800, 0).unwrap()).await.is_none());
+    let _ = std::fs::remove_dir_all(dir);
+  }
+
+  #[tokio::test]
+  async fn deselected_sections_are_left_alone() {
+    let now = Utc.timestamp_opt(1664611200, 0).unwrap();
+    let content = "[baidu]\nkey = \"id\"\nsecret_command = \"exit 1\"\n[dict]\npath = \"ecdict.csv\"";
+    let mut config: toml::Value = toml::from_str(content).unwrap();
+    assert!(matches!(resolve(&mut config, now).await, Err(TransError::CommandError)));
+
+    let mut config: toml::Value = toml::from_str(format!("engines = [\"Dict\"]\n{}", content).as_str()).unwrap();
+    assert!(resolve(&mut config, now).await.is_ok());
+    assert!(config["baidu"].get("secret").is_none());
+  }
+}
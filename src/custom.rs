@@ -0,0 +1,98 @@
+use reqwest::{Method, Version};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use crate::common::{Context, CustomConfig, TransReq, TransRes, TransError, TransResult, percent_encode, send_error, status_error};
+
+// `{src}`, `{source}` and `{target}` are replaced with the request values,
+// `escape` is applied to each value before it's inserted into the template.
+// The template is scanned once, braces in the inserted values are left alone.
+fn render<F>(template: &str, req: &TransReq, escape: F) -> String
+  where
+    F: Fn(&str) -> String,
+{
+  let mut result = String::with_capacity(template.len() + req.src_ref().len());
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    result.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let value = [("{src}", req.src_ref()), ("{source}", req.source_ref()), ("{target}", req.target_ref())]
+      .into_iter()
+      .find(|(placeholder, _)| rest.starts_with(placeholder));
+    match value {
+      Some((placeholder, value)) => {
+        result.push_str(escape(value).as_str());
+        rest = &rest[placeholder.len()..];
+      }
+      None => {
+        result.push('{');
+        rest = &rest[1..];
+      }
+    }
+  }
+  result.push_str(rest);
+  return result;
+}
+
+fn json_escape(str: &str) -> String {
+  let quoted = serde_json::to_string(str).unwrap();
+  return String::from(&quoted[1..quoted.len() - 1]);
+}
+
+pub async fn trans(context: &Context, config: &CustomConfig, req: &TransReq) -> TransResult<TransRes> {
+  let client = context.client_ref(format!("custom.{}", config.name()).as_str());
+  let method = Method::from_bytes(config.method().to_uppercase().as_bytes())
+    .map_err(|_| TransError::CustomConfig)?;
+  let url = render(config.url(), req, percent_encode);
+
+  let mut headers = HeaderMap::with_capacity(config.headers().len());
+  for (name, value) in config.headers() {
+    let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| TransError::CustomConfig)?;
+    let value = HeaderValue::from_str(render(value.expose(), req, str::to_string).as_str())
+      .map_err(|_| TransError::CustomConfig)?;
+    headers.insert(name, value);
+  }
+  let query: Vec<(&str, String)> = config.query().iter()
+    .map(|(name, value)| (name.as_str(), render(value.expose(), req, str::to_string)))
+    .collect();
+
+  let mut request_builder = client.request(method, url);
+  request_builder = request_builder.version(Version::HTTP_11);
+  request_builder = request_builder.headers(headers);
+  request_builder = request_builder.query(&query);
+  if !config.form().is_empty() {
+    let form: Vec<(&str, String)> = config.form().iter()
+      .map(|(name, value)| (name.as_str(), render(value.expose(), req, str::to_string)))
+      .collect();
+    request_builder = request_builder.form(&form);
+  } else if let Some(body) = config.body() {
+    request_builder = request_builder.body(render(body, req, json_escape));
+  }
+  let response = request_builder.send()
+    .await.map_err(send_error)?;
+  if !response.status().is_success() {
+    return Err(status_error(response.status()));
+  }
+  let response = response.json::<serde_json::Value>()
+    .await.map_err(|_| TransError::ResponseError)?;
+  let result = match response.pointer(config.result()) {
+    Some(serde_json::Value::String(result)) => result.clone(),
+    Some(result) => result.to_string(),
+    None => return Err(TransError::ResponseError),
+  };
+  return Ok(TransRes::create(result));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn placeholders_are_replaced_once() {
+    let req = TransReq::create("say {target} and \"{source}\"", "en", "zh");
+    assert_eq!(render("{src}|{source}|{target}|{other}|{", &req, str::to_string), "say {target} and \"{source}\"|en|zh|{other}|{");
+    assert_eq!(
+      render(r#"{"text":"{src}","to":"{target}"}"#, &req, json_escape),
+      r#"{"text":"say {target} and \"{source}\"","to":"zh"}"#
+    );
+    assert_eq!(render("/t?q={src}", &TransReq::create("a&b {src}", "en", "zh"), percent_encode), "/t?q=a%26b%20%7Bsrc%7D");
+  }
+}
@@ -0,0 +1,288 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use flate2::read::GzDecoder;
+use crate::common::{Context, TransReq, TransRes, TransError, TransResult};
+
+// ECDICT columns, see https://github.com/skywind3000/ECDICT
+const ECDICT_WORD: usize = 0;
+const ECDICT_PHONETIC: usize = 1;
+const ECDICT_DEFINITION: usize = 2;
+const ECDICT_TRANSLATION: usize = 3;
+const ECDICT_POS: usize = 4;
+
+fn ecdict_lines(field: &str) -> Vec<String> {
+  return field.split("\\n")
+    .flat_map(|line| line.split('\n'))
+    .map(|line| line.trim())
+    .filter(|line| !line.is_empty())
+    .map(String::from)
+    .collect();
+}
+
+// `n:46/v:54` -> `n. 46%  v. 54%`
+fn ecdict_pos(field: &str) -> Option<String> {
+  let pos: Vec<String> = field.split('/')
+    .filter_map(|pos| pos.split_once(':'))
+    .map(|(name, percent)| format!("{}. {}%", name, percent))
+    .collect();
+  return if pos.is_empty() { None } else { Some(pos.join("  ")) };
+}
+
+fn ecdict_lookup(path: &Path, word: &str) -> TransResult<TransRes> {
+  let mut reader = csv::ReaderBuilder::new()
+    .has_headers(true)
+    .flexible(true)
+    .from_path(path)
+    .map_err(|_| TransError::DictConfig)?;
+  for record in reader.records() {
+    let record = record.map_err(|_| TransError::DictConfig)?;
+    if !record.get(ECDICT_WORD).map(|w| w.eq_ignore_ascii_case(word)).unwrap_or(false) {
+      continue;
+    }
+    let translation = ecdict_lines(record.get(ECDICT_TRANSLATION).unwrap_or_default());
+    let definition = ecdict_lines(record.get(ECDICT_DEFINITION).unwrap_or_default());
+    let result = translation.first().or(definition.first()).ok_or(TransError::DictNotFound)?;
+    let mut res = TransRes::create(result.clone());
+    if let Some(phonetic) = record.get(ECDICT_PHONETIC).filter(|p| !p.is_empty()) {
+      res = res.with_phonetic(format!("[{}]", phonetic));
+    }
+    res = res.with_explains(translation.iter().skip(1).cloned().collect());
+    res = res.with_explains(definition);
+    if let Some(pos) = record.get(ECDICT_POS).and_then(ecdict_pos) {
+      res = res.with_explains(vec![pos]);
+    }
+    return Ok(res);
+  }
+  return Err(TransError::DictNotFound);
+}
+
+fn read_maybe_gz(path: &Path) -> std::io::Result<Vec<u8>> {
+  let mut data = Vec::new();
+  let file = File::open(path)?;
+  if path.extension().map(|ext| ext == "gz" || ext == "dz").unwrap_or(false) {
+    GzDecoder::new(file).read_to_end(&mut data)?;
+  } else {
+    let mut file = file;
+    file.read_to_end(&mut data)?;
+  }
+  return Ok(data);
+}
+
+fn first_existing(base: &Path, extensions: &[&str]) -> Option<PathBuf> {
+  return extensions.iter()
+    .map(|ext| base.with_extension(ext))
+    .find(|path| path.exists());
+}
+
+// the `.idx` and `.dict` files live next to the `.ifo` file and share its name
+fn stardict_lookup(path: &Path, word: &str) -> TransResult<TransRes> {
+  let ifo = std::fs::read_to_string(path).map_err(|_| TransError::DictConfig)?;
+  let option = |name: &str| ifo.lines()
+    .filter_map(|line| line.split_once('='))
+    .find(|(key, _)| key.trim() == name)
+    .map(|(_, value)| String::from(value.trim()));
+  let offset_bits = option("idxoffsetbits").unwrap_or_else(|| String::from("32"));
+  let type_sequence = option("sametypesequence");
+
+  let idx_path = first_existing(path, &["idx", "idx.gz"]).ok_or(TransError::DictConfig)?;
+  let dict_path = first_existing(path, &["dict", "dict.dz"]).ok_or(TransError::DictConfig)?;
+  let idx = read_maybe_gz(idx_path.as_path()).map_err(|_| TransError::DictConfig)?;
+  let offset_size = if offset_bits == "64" { 8 } else { 4 };
+
+  let (offset, size) = stardict_find(idx.as_slice(), offset_size, word)?;
+  let dict = read_maybe_gz(dict_path.as_path()).map_err(|_| TransError::DictConfig)?;
+  // both numbers come from the file, their sum may not even fit
+  let data = offset.checked_add(size)
+    .and_then(|end| dict.get(offset..end))
+    .ok_or(TransError::DictConfig)?;
+
+  let mut phonetic = None;
+  let mut explains = Vec::new();
+  for (kind, field) in stardict_fields(data, type_sequence.as_deref()) {
+    let text = String::from_utf8_lossy(field);
+    match kind {
+      't' => phonetic = Some(format!("[{}]", text.trim())),
+      'm' | 'l' | 'g' | 'x' | 'h' | 'y' | 'k' | 'w' => explains.extend(
+        strip_markup(text.as_ref()).lines()
+          .map(|line| line.trim())
+          .filter(|line| !line.is_empty())
+          .map(String::from)
+      ),
+      _ => {}
+    }
+  }
+  if explains.is_empty() {
+    return Err(TransError::DictNotFound);
+  }
+  let mut res = TransRes::create(explains.remove(0)).with_explains(explains);
+  if let Some(phonetic) = phonetic {
+    res = res.with_phonetic(phonetic);
+  }
+  return Ok(res);
+}
+
+// `.idx` entries are a `\0` terminated word, a big endian offset of `offset_size` bytes
+// and a big endian u32 size into the `.dict` file
+fn stardict_find(idx: &[u8], offset_size: usize, word: &str) -> TransResult<(usize, usize)> {
+  let mut position = 0;
+  while position < idx.len() {
+    let end = idx[position..].iter().position(|b| *b == 0).ok_or(TransError::DictConfig)? + position;
+    let entry = std::str::from_utf8(&idx[position..end]).unwrap_or_default();
+    let numbers = idx.get(end + 1..end + 1 + offset_size + 4).ok_or(TransError::DictConfig)?;
+    position = end + 1 + offset_size + 4;
+    if entry.eq_ignore_ascii_case(word) {
+      let offset = numbers[..offset_size].iter().fold(0u64, |acc, b| (acc << 8) | *b as u64) as usize;
+      let size = numbers[offset_size..].iter().fold(0u64, |acc, b| (acc << 8) | *b as u64) as usize;
+      return Ok((offset, size));
+    }
+  }
+  return Err(TransError::DictNotFound);
+}
+
+// lower case types are `\0` terminated text, upper case types are prefixed with a
+// big endian u32 size; the last field of a `sametypesequence` entry has neither
+fn stardict_fields<'a>(data: &'a [u8], type_sequence: Option<&str>) -> Vec<(char, &'a [u8])> {
+  let mut fields = Vec::new();
+  let mut rest = data;
+  let sequence: Option<Vec<char>> = type_sequence.map(|types| types.chars().collect());
+  let mut index = 0;
+  while !rest.is_empty() {
+    let (kind, last) = match sequence.as_ref() {
+      Some(types) => match types.get(index) {
+        Some(kind) => (*kind, index + 1 == types.len()),
+        None => break,
+      },
+      None => {
+        let kind = rest[0] as char;
+        rest = &rest[1..];
+        (kind, false)
+      }
+    };
+    index += 1;
+    let length = if last {
+      rest.len()
+    } else if kind.is_ascii_lowercase() {
+      rest.iter().position(|b| *b == 0).unwrap_or(rest.len())
+    } else {
+      let size = rest.get(..4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize).unwrap_or(0);
+      rest = rest.get(4..).unwrap_or_default();
+      size.min(rest.len())
+    };
+    fields.push((kind, &rest[..length]));
+    let skip = if !last && kind.is_ascii_lowercase() { 1 } else { 0 };
+    rest = rest.get(length + skip..).unwrap_or_default();
+  }
+  return fields;
+}
+
+fn strip_markup(text: &str) -> String {
+  let text = text.replace("<br>", "\n").replace("<br/>", "\n").replace("<br />", "\n");
+  let mut stripped = String::with_capacity(text.len());
+  let mut in_tag = false;
+  for char in text.chars() {
+    match char {
+      '<' => in_tag = true,
+      '>' if in_tag => in_tag = false,
+      _ if !in_tag => stripped.push(char),
+      _ => {}
+    }
+  }
+  return stripped;
+}
+
+pub async fn trans(context: &Context, req: &TransReq) -> TransResult<TransRes> {
+  let word = String::from(req.src_ref().trim());
+  let path = PathBuf::from(context.dict_path()?);
+  let stardict = match context.dict_format() {
+    Some("stardict") => true,
+    Some("ecdict") => false,
+    Some(_) => return Err(TransError::DictConfig),
+    None => path.extension().map(|ext| ext == "ifo").unwrap_or(false),
+  };
+  return tokio::task::spawn_blocking(move || {
+    if stardict {
+      stardict_lookup(path.as_path(), word.as_str())
+    } else {
+      ecdict_lookup(path.as_path(), word.as_str())
+    }
+  }).await.map_err(|_| TransError::DictConfig)?;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ECDICT: &str = "\
+word,phonetic,definition,translation,pos,collins
+apple,'æpl,n. fruit with red or yellow or green skin,n. 苹果\\n苹果树,n:100,3
+Run,rʌn,\"v. move fast\nn. a race\",vi. 跑\\nn. 赛跑,n:46/v:54,5
+";
+
+  fn fixture(name: &str, content: &[u8]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("translator-dict-{}", std::process::id()));
+    std::fs::create_dir_all(dir.as_path()).unwrap();
+    let path = dir.join(name);
+    std::fs::write(path.as_path(), content).unwrap();
+    return path;
+  }
+
+  #[test]
+  fn ecdict_rows_become_results() {
+    let path = fixture("ecdict.csv", ECDICT.as_bytes());
+    let res = ecdict_lookup(path.as_path(), "apple").unwrap();
+    assert_eq!(res.result(), "n. 苹果");
+    assert_eq!(res.phonetic(), Some("['æpl]"));
+    assert_eq!(res.explains(), ["苹果树", "n. fruit with red or yellow or green skin", "n. 100%"]);
+
+    let res = ecdict_lookup(path.as_path(), "run").unwrap();
+    assert_eq!(res.result(), "vi. 跑");
+    assert_eq!(res.explains(), ["n. 赛跑", "v. move fast", "n. a race", "n. 46%  v. 54%"]);
+    assert!(matches!(ecdict_lookup(path.as_path(), "pear"), Err(TransError::DictNotFound)));
+
+    assert_eq!(ecdict_pos("n:46/v:54").as_deref(), Some("n. 46%  v. 54%"));
+    assert_eq!(ecdict_pos(""), None);
+    let _ = std::fs::remove_file(path);
+  }
+
+  #[test]
+  fn stardict_idx_entries_are_found() {
+    let mut idx = Vec::new();
+    idx.extend_from_slice(b"apple\0");
+    idx.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 12]);
+    idx.extend_from_slice(b"pear\0");
+    idx.extend_from_slice(&[0, 0, 0, 12, 0, 0, 0, 9]);
+    assert_eq!(stardict_find(idx.as_slice(), 4, "Pear").unwrap(), (12, 9));
+    assert_eq!(stardict_find(idx.as_slice(), 4, "apple").unwrap(), (0, 12));
+    assert!(matches!(stardict_find(idx.as_slice(), 4, "plum"), Err(TransError::DictNotFound)));
+    // a truncated entry is a broken file
+    assert!(matches!(stardict_find(&idx[..idx.len() - 2], 4, "pear"), Err(TransError::DictConfig)));
+
+    let mut idx = Vec::new();
+    idx.extend_from_slice(b"apple\0");
+    idx.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3]);
+    assert_eq!(stardict_find(idx.as_slice(), 8, "apple").unwrap(), ((1 << 32) + 2, 3));
+  }
+
+  #[test]
+  fn stardict_sizes_past_the_end_are_rejected() {
+    let mut idx = Vec::new();
+    idx.extend_from_slice(b"apple\0");
+    idx.extend_from_slice(&[0xff; 12]);
+    fixture("huge.idx", idx.as_slice());
+    fixture("huge.dict", b"m\0");
+    let ifo = fixture("huge.ifo", b"StarDict's dict ifo file\nversion=3.0.0\nidxoffsetbits=64\n");
+    assert!(matches!(stardict_lookup(ifo.as_path(), "apple"), Err(TransError::DictConfig)));
+  }
+
+  #[test]
+  fn stardict_fields_are_split() {
+    let data = b"t'\xc3\xa6pl\0m\xe8\x8b\xb9\xe6\x9e\x9c\0W\0\0\0\x02ab";
+    let fields = stardict_fields(data, None);
+    assert_eq!(fields, vec![('t', "'æpl".as_bytes()), ('m', "苹果".as_bytes()), ('W', b"ab".as_slice())]);
+
+    // the last field of a `sametypesequence` entry runs to the end
+    let fields = stardict_fields(b"'\xc3\xa6pl\0fruit\0tree", Some("tm"));
+    assert_eq!(fields, vec![('t', "'æpl".as_bytes()), ('m', b"fruit\0tree".as_slice())]);
+  }
+}
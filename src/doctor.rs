@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+use crate::common::{Context, TransError, TransReq, TransRes, TransResult};
+use crate::{command, config, custom, trans_engine, ENGINES};
+
+const DOCTOR_TIMEOUT: u64 = 10;
+
+fn diagnose(error: &TransError) -> &'static str {
+  return match error {
+    TransError::AuthError => "credentials rejected, check `key`, `secret` and `token`",
+    TransError::ClockSkew => "request time rejected, the local clock is off, sync it with NTP",
+    TransError::QuotaExceeded => "quota exhausted, check the balance in the console",
+    TransError::RateLimited => "too many requests, wait a moment or raise the QPS in the console",
+    TransError::UnsupportedRegion => "region not supported by the service, check `region`",
+    TransError::DnsError => "host name can't be resolved, check the network, DNS or proxy",
+    TransError::TlsError => "TLS handshake failed, check the system certificates or an intercepting proxy",
+    TransError::RequestError => "request failed, check the network",
+    TransError::ResponseError => "unexpected response, the request or language pair may not be supported",
+    TransError::BaiduConfig | TransError::AliYunConfig | TransError::HuoShanConfig | TransError::TencentConfig
+    | TransError::YoudaoConfig | TransError::CaiYunConfig | TransError::NiuTransConfig | TransError::AwsConfig
+    | TransError::CustomConfig | TransError::DictConfig => "missing or invalid settings in the config",
+    TransError::CredentialError => "a credential command failed, run it by hand to see why",
+    TransError::SecretStoreError => "the secret store can't be read, check the passphrase",
+    TransError::ProfileNotFound => "the selected profile isn't defined",
+    TransError::ParseConfig => "the config can't be parsed, run `translator config path`",
+    TransError::HttpConfig => "invalid `proxy` URL or unreadable `ca_cert`",
+    TransError::CommandError | TransError::CommandTimeout => "the command failed or timed out, run it by hand",
+    TransError::DictNotFound => "the dictionary was read but doesn't contain `hello`",
+    _ => "unexpected failure",
+  };
+}
+
+fn print_error(name: &str, error: &TransError) {
+  println!("{:>8} {:<10} {:?}: {}", console::style("failed").red(), name, error, diagnose(error));
+}
+
+// config files holding credentials should only be readable by their owner
+#[cfg(unix)]
+fn check_permissions(path: &Path) {
+  use std::os::unix::fs::PermissionsExt;
+  let mode = match std::fs::metadata(path) {
+    Ok(metadata) => metadata.permissions().mode(),
+    Err(_) => return,
+  };
+  if mode & 0o077 == 0 {
+    println!("{:>8} {}", console::style("ok").green(), path.display());
+  } else {
+    println!(
+      "{:>8} {} is readable by others ({:o}), run `chmod 600 {}`",
+      console::style("warning").yellow(), path.display(), mode & 0o777, path.display()
+    );
+  }
+}
+
+#[cfg(not(unix))]
+fn check_permissions(path: &Path) {
+  println!("{:>8} {}", console::style("ok").green(), path.display());
+}
+
+// `translator doctor`, translates `hello` with every enabled engine and explains failures
+pub async fn doctor(explicit: Option<&Path>, profile: Option<&str>, overrides: toml::value::Table) -> TransResult<()> {
+  println!("config");
+  let loaded: Vec<PathBuf> = match config::load(explicit).await {
+    Ok((_, loaded)) => loaded,
+    Err(error) => {
+      print_error("config", &error);
+      return Err(error);
+    }
+  };
+  for path in loaded.iter() {
+    check_permissions(path.as_path());
+  }
+  if let Some(store) = dirs::data_dir().map(|dir| dir.join("translator").join("secrets.json")).filter(|path| path.is_file()) {
+    check_permissions(store.as_path());
+  }
+  let config = match config::parse_config(explicit, profile, overrides).await {
+    Ok(config) => config,
+    Err(error) => {
+      print_error("config", &error);
+      return Err(error);
+    }
+  };
+  let context = match Context::configure(TransReq::create("hello", "en", "zh"), config, Some(DOCTOR_TIMEOUT)) {
+    Ok(context) => context,
+    Err(error) => {
+      print_error("http", &error);
+      return Err(error);
+    }
+  };
+
+  println!("engines");
+  let mut names: Vec<String> = Vec::new();
+  let mut checks: Vec<futures::future::BoxFuture<TransResult<TransRes>>> = Vec::new();
+  for name in ENGINES.iter().map(|(name, _)| *name).filter(|name| context.builtin_enabled(name)) {
+    names.push(String::from(name));
+    checks.push(Box::pin(trans_engine(name, &context, context.req_ref())));
+  }
+  for custom in context.custom_configs().iter().filter(|custom| context.engine_enabled(custom.name())) {
+    names.push(String::from(custom.name()));
+    checks.push(Box::pin(custom::trans(&context, custom, context.req_ref())));
+  }
+  for command in context.command_configs().iter().filter(|command| context.engine_enabled(command.name())) {
+    names.push(String::from(command.name()));
+    checks.push(Box::pin(command::trans(&context, command, context.req_ref())));
+  }
+  if checks.is_empty() {
+    println!("{:>8} no engine configured, run `translator config init`", console::style("warning").yellow());
+    return Ok(());
+  }
+  let results = futures::future::join_all(checks).await;
+  let mut failure = None;
+  for (name, result) in names.iter().zip(results) {
+    match result {
+      Ok(res) => println!("{:>8} {:<10} hello -> {}", console::style("ok").green(), name, res.result()),
+      Err(error) => {
+        print_error(name.as_str(), &error);
+        failure.get_or_insert(error);
+      }
+    }
+  }
+  if let Some(skew) = context.clock_skew() {
+    println!(
+      "{:>8} the local clock is off by {}s, requests were signed with the server time, sync it with NTP",
+      console::style("warning").yellow(), skew.num_seconds()
+    );
+  }
+  // the first failed engine makes the exit status
+  return failure.map_or(Ok(()), Err);
+}
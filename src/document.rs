@@ -0,0 +1,136 @@
+use std::io::IsTerminal;
+use std::path::Path;
+use std::time::Duration;
+use crate::common::{CommandConfig, Context, CustomConfig, TransError, TransReq, TransRes, TransResult, write_atomic};
+use crate::{chunk, command, custom, trans_engine, ENGINES};
+
+enum Engine<'a> {
+  Builtin(&'static str),
+  Custom(&'a CustomConfig),
+  Command(&'a CommandConfig),
+}
+
+impl<'a> Engine<'a> {
+  // a document is translated by exactly one engine, picked with `--engine` or the only one configured
+  fn select(context: &'a Context) -> TransResult<Engine<'a>> {
+    let mut engines: Vec<Engine> = ENGINES.iter()
+      .filter(|(name, _)| context.builtin_enabled(name))
+      .map(|(name, _)| Engine::Builtin(name))
+      .collect();
+    engines.extend(context.custom_configs().iter().filter(|custom| context.engine_enabled(custom.name())).map(Engine::Custom));
+    engines.extend(context.command_configs().iter().filter(|command| context.engine_enabled(command.name())).map(Engine::Command));
+    if engines.len() != 1 {
+      return Err(TransError::EngineNotSelected);
+    }
+    return Ok(engines.remove(0));
+  }
+
+  // built-in engines are paced like the chunks of a long text
+  fn interval(&self) -> Duration {
+    return match self {
+      Engine::Builtin(name) => chunk::interval(name),
+      _ => Duration::ZERO,
+    };
+  }
+
+  async fn trans(&self, context: &Context, req: &TransReq) -> TransResult<TransRes> {
+    return match self {
+      Engine::Builtin(name) => trans_engine(name, context, req).await,
+      Engine::Custom(custom) => custom::trans(context, custom, req).await,
+      Engine::Command(command) => command::trans(context, command, req).await,
+    };
+  }
+}
+
+// runs of non-blank lines, each paired with whether it is translated, the pieces
+// concatenate back to `text` so blank lines and line endings survive as they are
+fn paragraphs(text: &str) -> Vec<(bool, &str)> {
+  let mut pieces: Vec<(bool, &str)> = Vec::new();
+  let mut start = 0;
+  for line in text.split_inclusive('\n') {
+    let blank = line.trim().is_empty();
+    let end = start + line.len();
+    match pieces.last_mut() {
+      Some((translate, piece)) if *translate != blank => *piece = &text[start - piece.len()..end],
+      _ => pieces.push((!blank, line)),
+    }
+    start = end;
+  }
+  return pieces;
+}
+
+fn progress(done: usize, total: usize) {
+  if std::io::stderr().is_terminal() {
+    eprint!("\r{} {}/{}", console::style("translating").cyan(), done, total);
+  }
+}
+
+// renamed over `path` once on disk, an existing output keeps its permissions
+async fn write_output(path: &Path, content: String) -> TransResult<()> {
+  let path = path.to_path_buf();
+  return tokio::task::spawn_blocking(move || {
+    let permissions = std::fs::metadata(path.as_path()).ok().map(|metadata| metadata.permissions());
+    return write_atomic(path.as_path(), content.as_bytes(), permissions).map_err(|_| TransError::OutputError);
+  }).await.map_err(|_| TransError::OutputError)?;
+}
+
+// `--input FILE`, paragraph by paragraph, to `--output FILE` or stdout
+pub async fn translate(context: &Context, input: &Path, output: Option<&Path>) -> TransResult<()> {
+  let engine = Engine::select(context)?;
+  let text = tokio::fs::read_to_string(input).await.map_err(|_| TransError::InputError)?;
+  let pieces = paragraphs(text.as_str());
+  let total = pieces.iter().filter(|(translate, _)| *translate).count();
+  let req = context.req_ref();
+  let mut result = String::with_capacity(text.len());
+  let mut done = 0;
+  progress(done, total);
+  for (translate, piece) in pieces {
+    if !translate {
+      result.push_str(piece);
+      continue;
+    }
+    if done > 0 {
+      tokio::time::sleep(engine.interval()).await;
+    }
+    let (lead, body, trail) = chunk::surrounding(piece);
+    let paragraph = TransReq::create(body, req.source_ref(), req.target_ref());
+    let res = engine.trans(context, &paragraph).await;
+    if res.is_err() && std::io::stderr().is_terminal() {
+      eprintln!();
+    }
+    result.push_str(lead);
+    result.push_str(res?.result());
+    result.push_str(trail);
+    done += 1;
+    progress(done, total);
+  }
+  if std::io::stderr().is_terminal() {
+    eprintln!();
+  }
+  return match output {
+    Some(path) => write_output(path, result).await,
+    None => {
+      print!("{}", result);
+      Ok(())
+    }
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn paragraphs_keep_blank_lines() {
+    let text = "first line\nsecond line\n\n  \nnext\r\n\nlast";
+    let pieces = paragraphs(text);
+    assert_eq!(pieces, vec![
+      (true, "first line\nsecond line\n"),
+      (false, "\n  \n"),
+      (true, "next\r\n"),
+      (false, "\n"),
+      (true, "last"),
+    ]);
+    assert_eq!(pieces.iter().map(|(_, piece)| *piece).collect::<String>(), text);
+  }
+}
@@ -1,8 +1,13 @@
-use chrono::Utc;
-use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue, HOST};
+use reqwest::StatusCode;
+use reqwest::header::{AUTHORIZATION, HeaderValue};
 use serde::{Serialize, Deserialize};
-use crate::{Context, HeaderName, TransError, TransRes, TransResult};
-use crate::common::{hex_byte, sha2_hmac, sha2_str};
+use crate::{Context, TransError, TransReq, TransRes, TransResult};
+use crate::common::{classify_error, json_text, read_json, retry_on_skew, send_error, server_date, with_account};
+use crate::sign::{CanonicalRequest, V4Signer};
+
+// `TextList` takes at most 16 texts
+const HUOSHAN_BATCH_SIZE: usize = 16;
+const HUOSHAN_BATCH_LENGTH: usize = 5000;
 
 #[derive(Serialize, Debug)]
 pub struct HuoShanReq {
@@ -14,10 +19,10 @@ pub struct HuoShanReq {
 }
 
 impl HuoShanReq {
-  fn create(src: &str, target: &str) -> Self {
+  fn create(texts: &[String], target: &str) -> Self {
     return HuoShanReq {
       target: String::from(target),
-      data: vec![String::from(src)],
+      data: texts.to_vec(),
     };
   }
 }
@@ -29,8 +34,8 @@ pub struct HuoShanRes {
 }
 
 impl HuoShanRes {
-  fn to_trans_res(&self) -> TransRes {
-    return TransRes::create(self.data.first().map(|res| res.dst.clone()).unwrap());
+  fn to_trans_list(&self) -> Vec<TransRes> {
+    return self.data.iter().map(|res| TransRes::create(res.dst.clone())).collect();
   }
 }
 
@@ -43,53 +48,61 @@ struct HuoShanInnerRes {
   dst: String,
 }
 
-pub async fn trans(context: &Context) -> TransResult<TransRes> {
-  let req = context.req_ref();
-  let client = context.client_ref();
-  let current_time = Utc::now();
-  let current_data1 = current_time.format("%Y%m%d").to_string();
-  let current_data2 = current_time.format("%Y%m%dT%H%M%SZ").to_string();
-  let body = HuoShanReq::create(req.src_ref(), req.target_ref());
-  let body_str = serde_json::to_string(&body).map_err(|_| TransError::SerdeError)?;
+// `{"ResponseMetadata": {"Error": {"Code": "SignatureDoesNotMatch", "Message": ".."}}}`
+fn error(status: StatusCode, body: &serde_json::Value) -> TransError {
+  let error = &body["ResponseMetadata"]["Error"];
+  return classify_error(status, json_text(&error["Code"]).as_str(), json_text(&error["Message"]).as_str());
+}
 
-  let mut sign_request = Vec::with_capacity(6);
-  sign_request.push("POST");
-  sign_request.push("/");
-  sign_request.push("Action=TranslateText&Version=2020-06-01");
-  let canonical_headers = format!("content-type:application/json; charset=utf-8\nhost:open.volcengineapi.com\nx-date:{}\n", current_data2.as_str());
-  sign_request.push(canonical_headers.as_str());
-  sign_request.push("content-type;host;x-date");
-  let body_hash = hex_byte(sha2_str(body_str.as_str()).as_slice());
-  sign_request.push(body_hash.as_ref());
-  let sign_request_str = sign_request.join("\n");
+pub async fn trans(context: &Context, req: &TransReq) -> TransResult<TransRes> {
+  let texts = [String::from(req.src_ref())];
+  let mut list = trans_list(context, req.target_ref(), &texts).await?;
+  return list.pop().ok_or(TransError::ResponseError);
+}
 
-  let mut hmac_data = Vec::with_capacity(4);
-  hmac_data.push("HMAC-SHA256");
-  hmac_data.push(current_data2.as_str());
-  let credential_scope = format!("{}/cn-north-1/translate/request", current_data1.as_str());
-  hmac_data.push(credential_scope.as_ref());
-  let request_hash = hex_byte(sha2_str(sign_request_str.as_str()).as_slice());
-  hmac_data.push(request_hash.as_ref());
-  let hmac_data_str = hmac_data.join("\n");
+// at most `HUOSHAN_BATCH_SIZE` segments and `HUOSHAN_BATCH_LENGTH` characters per request
+pub async fn trans_batch(context: &Context, segments: &[String]) -> TransResult<Vec<TransRes>> {
+  let target = context.req_ref().target_ref();
+  let mut results = Vec::with_capacity(segments.len());
+  let mut start = 0;
+  while start < segments.len() {
+    let mut end = start + 1;
+    let mut length = segments[start].chars().count();
+    while end < segments.len() && end - start < HUOSHAN_BATCH_SIZE
+      && length + segments[end].chars().count() <= HUOSHAN_BATCH_LENGTH {
+      length += segments[end].chars().count();
+      end += 1;
+    }
+    results.extend(trans_list(context, target, &segments[start..end]).await?);
+    start = end;
+  }
+  return Ok(results);
+}
 
-  let mut hmac_key = sha2_hmac(context.huoshan_secret()?.as_bytes(), current_data1.as_bytes())?;
-  hmac_key = sha2_hmac(hmac_key.as_slice(), "cn-north-1".as_bytes())?;
-  hmac_key = sha2_hmac(hmac_key.as_slice(), "translate".as_bytes())?;
-  hmac_key = sha2_hmac(hmac_key.as_slice(), "request".as_bytes())?;
-  let signature = sha2_hmac(hmac_key.as_slice(), hmac_data_str.as_bytes())?;
+async fn trans_list(context: &Context, target: &str, texts: &[String]) -> TransResult<Vec<TransRes>> {
+  return with_account(context, "huoshan", context.huoshan_accounts(), |account| {
+    retry_on_skew(context, move || trans_once(context, target, texts, account))
+  }).await;
+}
 
-  let mut authorization = String::from("HMAC-SHA256 Credential=");
-  authorization.push_str(context.huoshan_key()?);
-  authorization.push_str("/");
-  authorization.push_str(credential_scope.as_str());
-  authorization.push_str(", SignedHeaders=content-type;host;x-date");
-  authorization.push_str(", Signature=");
-  authorization.push_str(hex_byte(signature.as_slice()).as_ref());
+async fn trans_once(context: &Context, target: &str, texts: &[String], account: usize) -> TransResult<Vec<TransRes>> {
+  let client = context.client_ref("huoshan");
+  let current_time = context.now();
+  let body = HuoShanReq::create(texts, target);
+  let body_str = serde_json::to_string(&body).map_err(|_| TransError::SerdeError)?;
 
-  let mut headers = HeaderMap::with_capacity(4);
-  headers.insert(HeaderName::from_static("x-date"), HeaderValue::from_str(current_data2.as_str()).unwrap());
-  headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json; charset=utf-8").unwrap());
-  headers.insert(HOST, HeaderValue::from_str("open.volcengineapi.com").unwrap());
+  let mut sign_request = CanonicalRequest::create("POST", "/", body_str.as_str())
+    .query("Action", "TranslateText")
+    .query("Version", "2020-06-01")
+    .header("content-type", "application/json; charset=utf-8")
+    .header("host", "open.volcengineapi.com")
+    .header("x-date", V4Signer::timestamp(&current_time).as_str());
+  if let Some(token) = context.huoshan_token(account) {
+    sign_request = sign_request.header("x-security-token", token);
+  }
+  let authorization = V4Signer::huoshan("cn-north-1", "translate")
+    .sign(context.huoshan_key(account)?, context.huoshan_secret(account)?, &sign_request, &current_time)?;
+  let mut headers = sign_request.header_map().map_err(|_| TransError::HuoShanConfig)?;
   headers.insert(AUTHORIZATION, HeaderValue::from_str(authorization.as_str()).unwrap());
 
   let query = [
@@ -101,9 +114,13 @@ pub async fn trans(context: &Context) -> TransResult<TransRes> {
   request_builder = request_builder.body(body_str);
   request_builder = request_builder.headers(headers);
   let response = request_builder.send()
-    .await.map_err(|_| TransError::RequestError)?
-    .json::<HuoShanRes>()
-    .await.map_err(|_| TransError::ResponseError)?
-    .to_trans_res();
+    .await.map_err(send_error)?;
+  let date = server_date(&response);
+  let response = read_json::<HuoShanRes>(response, error)
+    .await.map_err(|error| context.correct_clock(error, date))?
+    .to_trans_list();
+  if response.len() != texts.len() {
+    return Err(TransError::ResponseError);
+  }
   return Ok(response);
 }
\ No newline at end of file
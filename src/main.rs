@@ -1,3 +1,5 @@
+#![allow(clippy::needless_return)]
+
 extern crate core;
 
 mod baidu;
@@ -5,46 +7,111 @@ mod common;
 mod aliyun;
 mod huoshan;
 mod tencent;
+mod youdao;
+mod caiyun;
+mod niutrans;
+mod aws;
+mod custom;
+mod command;
+mod dict;
+mod sign;
+mod credential;
+mod secrets;
+mod config;
+mod wizard;
+mod doctor;
+mod batch;
+mod chunk;
+mod document;
 
 use std::future::Future;
-use std::path::{Path, PathBuf};
-use clap::Parser;
+use std::path::PathBuf;
+use clap::{CommandFactory, ErrorKind, Parser, Subcommand};
 use futures::FutureExt;
-use reqwest::Client;
-use reqwest::header::HeaderName;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::mpsc::Sender;
-use crate::common::{Config, Context, TransError, TransReq, TransRes, TransResult};
+use crate::common::{ColorChoice, Config, Context, OutputFormat, TransError, TransReq, TransRes, TransResult, retry_on_rate_limit};
 
 #[tokio::main]
 async fn main() {
   let command: TransCommand = TransCommand::parse();
+  command.validate();
   if let Err(error) = inner_main(&command).await {
     println!("{:?}", error);
+    std::process::exit(1);
   }
   std::process::exit(0);
 }
 
 async fn inner_main(command: &TransCommand) -> TransResult<()> {
-  let (tx, mut rx) = tokio::sync::mpsc::channel(4);
-  let context = prepare_trans(&command).await?;
-  let mut trans_tasks = Vec::with_capacity(5);
-  if context.baidu_enabled() {
-    trans_tasks.push(trans(" BaiDu ", tx.clone(), baidu::trans(&context)).boxed());
+  if let Some(action) = command.action.as_ref() {
+    return run_action(command, action).await;
+  }
+  let context = prepare_trans(command).await?;
+  if let Some(input) = command.input.as_deref() {
+    return document::translate(&context, input, command.output.as_deref()).await;
   }
-  if context.huoshan_enabled() {
-    trans_tasks.push(trans("HuoShan", tx.clone(), huoshan::trans(&context)).boxed());
+  if let Some(segments) = command.segments().await? {
+    return trans_segments(&context, segments).await;
+  }
+  let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+  let mut trans_tasks = Vec::with_capacity(10);
+  for (name, title) in ENGINES.iter().filter(|(name, _)| context.builtin_enabled(name)) {
+    trans_tasks.push(trans(title, tx.clone(), trans_engine(name, &context, context.req_ref())).boxed());
   }
-  if context.aliyun_enabled() {
-    trans_tasks.push(trans(" AliYun", tx.clone(), aliyun::trans(&context)).boxed());
+  for custom in context.custom_configs().iter().filter(|custom| context.engine_enabled(custom.name())) {
+    let name = format!("{:^7}", custom.name());
+    trans_tasks.push(trans(name, tx.clone(), custom::trans(&context, custom, context.req_ref())).boxed());
   }
-  if context.tencent_enabled() {
-    trans_tasks.push(trans("Tencent", tx.clone(), tencent::trans(&context)).boxed());
+  for command in context.command_configs().iter().filter(|command| context.engine_enabled(command.name())) {
+    let name = format!("{:^7}", command.name());
+    trans_tasks.push(trans(name, tx.clone(), command::trans(&context, command, context.req_ref())).boxed());
   }
   drop(tx);
-  let print_task = async {
+  let format = context.config_ref().format();
+  let print_task = async move {
     while let Some((name, res)) = rx.recv().await {
-      print_trans_res(name.as_str(), res).await;
+      match format {
+        OutputFormat::Text => print_trans_res(name.as_str(), res).await,
+        OutputFormat::Json => print_trans_json(name.as_str(), res).await,
+      }
+    }
+    Ok(())
+  };
+  trans_tasks.push(print_task.boxed());
+  futures::future::join_all(trans_tasks).await;
+  Ok(())
+}
+
+// every engine translates all segments, results of one engine are printed together
+async fn trans_segments(context: &Context, segments: Vec<String>) -> TransResult<()> {
+  let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+  let segments = segments.as_slice();
+  let mut trans_tasks = Vec::with_capacity(10);
+  for (name, title) in ENGINES.iter().filter(|(name, _)| context.builtin_enabled(name)) {
+    trans_tasks.push(trans(title, tx.clone(), batch::trans_batch(name, context, segments).map(Ok)).boxed());
+  }
+  for custom in context.custom_configs().iter().filter(|custom| context.engine_enabled(custom.name())) {
+    let name = format!("{:^7}", custom.name());
+    let results = batch::trans_each(context, segments, move |req| async move { custom::trans(context, custom, &req).await });
+    trans_tasks.push(trans(name, tx.clone(), results.map(Ok)).boxed());
+  }
+  for command in context.command_configs().iter().filter(|command| context.engine_enabled(command.name())) {
+    let name = format!("{:^7}", command.name());
+    let results = batch::trans_each(context, segments, move |req| async move { command::trans(context, command, &req).await });
+    trans_tasks.push(trans(name, tx.clone(), results.map(Ok)).boxed());
+  }
+  drop(tx);
+  let format = context.config_ref().format();
+  let print_task = async move {
+    while let Some((name, results)) = rx.recv().await {
+      let results: Vec<TransResult<TransRes>> = results?;
+      for (index, res) in results.into_iter().enumerate() {
+        match format {
+          OutputFormat::Text => print_trans_res(name.as_str(), res).await,
+          OutputFormat::Json => print_segment_json(name.as_str(), index, segments[index].as_str(), res).await,
+        }
+      }
     }
     Ok(())
   };
@@ -53,14 +120,53 @@ async fn inner_main(command: &TransCommand) -> TransResult<()> {
   Ok(())
 }
 
-async fn trans<F>(name: &str, sender: Sender<(String, TransResult<TransRes>)>, future: F) -> TransResult<()>
+async fn trans<N, F, T>(name: N, sender: Sender<(String, TransResult<T>)>, future: F) -> TransResult<()>
   where
-    F: Future<Output=TransResult<TransRes>>,
+    N: ToString,
+    F: Future<Output=TransResult<T>>,
 {
   let res = future.await;
   return sender.send((name.to_string(), res)).await.map_err(|_| TransError::ChannelError);
 }
 
+// built-in engines by config name, with their padded titles
+const ENGINES: [(&str, &str); 9] = [
+  ("baidu", " BaiDu "),
+  ("huoshan", "HuoShan"),
+  ("aliyun", " AliYun"),
+  ("tencent", "Tencent"),
+  ("youdao", " Youdao"),
+  ("caiyun", " CaiYun"),
+  ("niutrans", "XiaoNiu"),
+  ("aws", "  AWS  "),
+  ("dict", "  Dict "),
+];
+
+// the built-in engine called `name` in the config, texts over its limit are split and
+// a rate limited request is sent again
+pub async fn trans_engine(name: &str, context: &Context, req: &TransReq) -> TransResult<TransRes> {
+  return match chunk::limit(name) {
+    Some(limit) if !limit.fits(req.src_ref()) => chunk::trans(name, context, req, limit).await,
+    _ => retry_on_rate_limit(|| trans_single(name, context, req)).await,
+  };
+}
+
+// one request to the built-in engine `name`
+pub async fn trans_single(name: &str, context: &Context, req: &TransReq) -> TransResult<TransRes> {
+  return match name {
+    "baidu" => baidu::trans(context, req).await,
+    "huoshan" => huoshan::trans(context, req).await,
+    "aliyun" => aliyun::trans(context, req).await,
+    "tencent" => tencent::trans(context, req).await,
+    "youdao" => youdao::trans(context, req).await,
+    "caiyun" => caiyun::trans(context, req).await,
+    "niutrans" => niutrans::trans(context, req).await,
+    "aws" => aws::trans(context, req).await,
+    "dict" => dict::trans(context, req).await,
+    _ => Err(TransError::ParseConfig),
+  };
+}
+
 async fn print_trans_res(name: &str, res: TransResult<TransRes>) {
   let mut writer = tokio::io::BufWriter::new(tokio::io::stdout());
   match res {
@@ -68,6 +174,12 @@ async fn print_trans_res(name: &str, res: TransResult<TransRes>) {
       let _ = writer.write_all(
         format!("{}: {}\n", console::style(name).yellow(), console::style(answer.result()).white()).as_bytes()
       ).await;
+      if let Some(phonetic) = answer.phonetic() {
+        let _ = writer.write_all(format!("         {}\n", console::style(phonetic).cyan()).as_bytes()).await;
+      }
+      for explain in answer.explains() {
+        let _ = writer.write_all(format!("         {}\n", console::style(explain).dim()).as_bytes()).await;
+      }
     }
     Err(error) => {
       let _ = writer.write_all(
@@ -78,70 +190,223 @@ async fn print_trans_res(name: &str, res: TransResult<TransRes>) {
   let _ = writer.flush().await;
 }
 
-async fn prepare_trans(command: &TransCommand) -> TransResult<Context> {
-  let config = parse_config(command).await.map_err(|_| TransError::ParseConfig)?;
-  return Ok(Context::create(command.trans_req(), Client::new(), config));
-}
-
-async fn parse_config(command: &TransCommand) -> TransResult<Config> {
-  let config = if command.config_path().is_some() {
-    let config_path = command.config_path().unwrap();
-    config_from_command_path(config_path).await
-  } else {
-    config_from_default_path().await
+// one JSON object per line, for scripts
+async fn print_trans_json(name: &str, res: TransResult<TransRes>) {
+  let mut line = match res {
+    Ok(answer) => serde_json::to_value(answer).unwrap_or_default(),
+    Err(error) => serde_json::json!({ "error": format!("{:?}", error) }),
   };
-  return config;
+  if let Some(object) = line.as_object_mut() {
+    object.insert(String::from("engine"), serde_json::Value::String(String::from(name.trim())));
+  }
+  let mut writer = tokio::io::BufWriter::new(tokio::io::stdout());
+  let _ = writer.write_all(format!("{}\n", line).as_bytes()).await;
+  let _ = writer.flush().await;
 }
 
-async fn config_from_command_path<P: AsRef<Path>>(path: P) -> TransResult<Config> {
-  let meta = tokio::fs::metadata(path.as_ref())
-    .await.map_err(|_| TransError::ParseConfig)?;
-  if meta.is_dir() {
-    return Err(TransError::ParseConfig);
+// `--batch --format json`, one line per segment and engine
+async fn print_segment_json(name: &str, index: usize, src: &str, res: TransResult<TransRes>) {
+  let mut line = match res {
+    Ok(answer) => serde_json::to_value(answer).unwrap_or_default(),
+    Err(error) => serde_json::json!({ "error": format!("{:?}", error) }),
+  };
+  if let Some(object) = line.as_object_mut() {
+    object.insert(String::from("engine"), serde_json::Value::String(String::from(name.trim())));
+    object.insert(String::from("index"), serde_json::Value::from(index));
+    object.insert(String::from("src"), serde_json::Value::String(String::from(src)));
   }
-  return parse_config_from_file(path).await;
+  let mut writer = tokio::io::BufWriter::new(tokio::io::stdout());
+  let _ = writer.write_all(format!("{}\n", line).as_bytes()).await;
+  let _ = writer.flush().await;
 }
 
-async fn config_from_default_path() -> TransResult<Config> {
-  let config_path = dirs::config_dir()
-    .map(|path| path.join("translator.toml"))
-    .unwrap_or(PathBuf::from("./translator.toml"));
-  if tokio::fs::metadata(config_path.as_path()).await.is_ok() {
-    return parse_config_from_file(config_path.as_path()).await;
+async fn prepare_trans(command: &TransCommand) -> TransResult<Context> {
+  let config = config::parse_config(command.config_path().as_deref(), command.profile.as_deref(), command.overrides()).await?;
+  match config.color() {
+    ColorChoice::Always => console::set_colors_enabled(true),
+    ColorChoice::Never => console::set_colors_enabled(false),
+    ColorChoice::Auto => {}
   }
-  return Err(TransError::ParseConfig);
+  let req = command.trans_req(&config);
+  return Context::configure(req, config, None);
 }
 
-async fn parse_config_from_file<P: AsRef<Path>>(path: P) -> TransResult<Config> {
-  let content = tokio::fs::read(path)
-    .await.map_err(|_| TransError::ParseConfig)?;
-  return toml::from_slice(content.as_slice()).map_err(|_| TransError::ParseConfig);
+async fn run_action(command: &TransCommand, action: &Action) -> TransResult<()> {
+  return match action {
+    Action::Config { action: ConfigAction::Path } => config::print_path(command.config_path().as_deref()).await,
+    Action::Config { action: ConfigAction::Init } => wizard::init(command.config_path().as_deref()).await,
+    Action::Doctor => doctor::doctor(command.config_path().as_deref(), command.profile.as_deref(), command.overrides()).await,
+    Action::Secrets { action: SecretsAction::Set { name } } => secrets::set(name),
+    Action::Secrets { action: SecretsAction::Get { name } } => secrets::get(name),
+    Action::Secrets { action: SecretsAction::Rm { name } } => secrets::remove(name),
+  };
 }
 
 #[derive(Debug, Parser)]
 struct TransCommand {
+  #[clap(subcommand)]
+  action: Option<Action>,
+
   word: Vec<String>,
 
-  #[clap(long = "source", short = 's', default_value = "auto")]
-  source: String,
+  /// Source language [default: auto]
+  #[clap(long = "source", short = 's')]
+  source: Option<String>,
 
-  #[clap(long = "target", short = 't', default_value = "zh")]
-  target: String,
+  /// Target language [default: zh]
+  #[clap(long = "target", short = 't')]
+  target: Option<String>,
 
-  #[clap(long = "config", short = 'c')]
+  #[clap(long = "config", short = 'c', global = true)]
   config: Option<PathBuf>,
 
+  /// Profile from `[profile.<name>]`, defaults to $TRANSLATOR_PROFILE
+  #[clap(long = "profile", short = 'p', global = true)]
+  profile: Option<String>,
+
+  /// Engines to use, repeatable [default: every configured engine]
+  #[clap(long = "engine", short = 'e')]
+  engine: Vec<String>,
+
+  /// Output format [default: text]
+  #[clap(long = "format", short = 'f', value_parser = ["text", "json"])]
+  format: Option<String>,
+
+  /// Request timeout in seconds
+  #[clap(long = "timeout")]
+  timeout: Option<u64>,
+
+  /// Domain such as it, medicine, finance, law or ecommerce, mapped to each engine
+  #[clap(long = "domain", short = 'd')]
+  domain: Option<String>,
+
+  /// When to color the output [default: auto]
+  #[clap(long = "color", value_parser = ["auto", "always", "never"])]
+  color: Option<String>,
+
+  /// Translate every word as a separate segment
+  #[clap(long = "each")]
+  each: bool,
+
+  /// Translate every line of FILE, or the strings of a JSON array, `-` reads stdin
+  #[clap(long = "batch", value_name = "FILE", conflicts_with = "each")]
+  batch: Option<PathBuf>,
+
+  /// Translate a plain text document with a single engine, paragraph by paragraph
+  #[clap(long = "input", short = 'i', value_name = "FILE", conflicts_with_all = &["each", "batch"])]
+  input: Option<PathBuf>,
+
+  /// Write the translated document to FILE instead of stdout
+  #[clap(long = "output", short = 'o', value_name = "FILE", requires = "input")]
+  output: Option<PathBuf>,
+
   // #[clap(long = "log-level", default_value = "warn")]
   // log_level: Level,
 }
 
 impl TransCommand {
-  fn trans_req(&self) -> TransReq {
+  // `--input` and `<WORD>` are exclusive, checked here because declaring the conflict
+  // in clap 3 stops it from enforcing `requires = "input"` on `--output`
+  fn validate(&self) {
+    if self.input.is_some() && !self.word.is_empty() {
+      TransCommand::command()
+        .error(ErrorKind::ArgumentConflict, "The argument '--input <FILE>' cannot be used with '<WORD>...'")
+        .exit();
+    }
+  }
+
+  fn trans_req(&self, config: &Config) -> TransReq {
     let src = self.word.join(" ");
-    return TransReq::create(src.as_str(), self.source.as_str(), self.target.as_str());
+    let source = config.source().unwrap_or("auto");
+    let target = config.target().unwrap_or("zh");
+    return TransReq::create(src.as_str(), source, target);
+  }
+
+  // flags given on the command line, laid over the config files
+  fn overrides(&self) -> toml::value::Table {
+    let mut overrides = toml::value::Table::new();
+    let strings = [
+      ("source", self.source.as_ref()),
+      ("target", self.target.as_ref()),
+      ("format", self.format.as_ref()),
+      ("color", self.color.as_ref()),
+      ("domain", self.domain.as_ref())
+    ];
+    for (name, value) in strings {
+      if let Some(value) = value {
+        overrides.insert(String::from(name), toml::Value::String(value.clone()));
+      }
+    }
+    if let Some(timeout) = self.timeout {
+      overrides.insert(String::from("timeout"), toml::Value::Integer(timeout as i64));
+    }
+    if !self.engine.is_empty() {
+      let engines = self.engine.iter().map(|engine| toml::Value::String(engine.clone())).collect();
+      overrides.insert(String::from("engines"), toml::Value::Array(engines));
+    }
+    return overrides;
+  }
+
+  // the segments of `--each` or `--batch`, `None` translates the words as one text
+  async fn segments(&self) -> TransResult<Option<Vec<String>>> {
+    if self.each {
+      return Ok(Some(self.word.clone()));
+    }
+    let path = match self.batch.as_ref() {
+      Some(path) => path,
+      None => return Ok(None),
+    };
+    let content = if path.as_os_str() == "-" {
+      let mut content = String::new();
+      tokio::io::AsyncReadExt::read_to_string(&mut tokio::io::stdin(), &mut content)
+        .await.map_err(|_| TransError::InputError)?;
+      content
+    } else {
+      tokio::fs::read_to_string(path).await.map_err(|_| TransError::InputError)?
+    };
+    return Ok(Some(batch::parse_segments(content.as_str())));
   }
 
   fn config_path(&self) -> Option<PathBuf> {
     return self.config.clone();
   }
+}
+
+#[derive(Debug, Subcommand)]
+enum Action {
+  /// Inspect the configuration
+  Config {
+    #[clap(subcommand)]
+    action: ConfigAction,
+  },
+
+  /// Check the config, credentials and connectivity of every engine
+  Doctor,
+
+  /// Manage the encrypted secret store
+  Secrets {
+    #[clap(subcommand)]
+    action: SecretsAction,
+  },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigAction {
+  /// Show where configuration files are searched and which were loaded
+  Path,
+
+  /// Interactively write a config file, to `--config` or the user config
+  Init,
+}
+
+#[derive(Debug, Subcommand)]
+enum SecretsAction {
+  /// Store a secret, e.g. `baidu.secret`
+  Set { name: String },
+
+  /// Print a stored secret
+  Get { name: String },
+
+  /// Remove a stored secret
+  Rm { name: String },
 }
\ No newline at end of file
@@ -0,0 +1,61 @@
+use reqwest::{StatusCode, Version};
+use serde::Deserialize;
+use crate::common::{Context, TransReq, TransRes, TransResult, classify_error, json_text, read_json, send_error, status_error, with_account};
+
+#[derive(Deserialize, Debug)]
+pub struct NiuTransRes {
+  // from: String,
+
+  // to: String,
+
+  #[serde(rename(deserialize = "tgt_text"))]
+  dst: Option<String>,
+
+  #[serde(default)]
+  error_code: serde_json::Value,
+
+  #[serde(default)]
+  error_msg: serde_json::Value,
+}
+
+impl NiuTransRes {
+  fn to_trans_res(&self) -> TransResult<TransRes> {
+    if let Some(dst) = self.dst.as_ref() {
+      return Ok(TransRes::create(dst.clone()));
+    }
+    return Err(classify_error(StatusCode::OK, json_text(&self.error_code).as_str(), json_text(&self.error_msg).as_str()));
+  }
+}
+
+fn niutrans_lang(lang: &str) -> &str {
+  return match lang {
+    "zh-CN" | "zh-Hans" | "zh-CHS" => "zh",
+    "zh-TW" | "zh-Hant" | "zh-CHT" => "cht",
+    "jp" => "ja",
+    "kor" => "ko",
+    _ => lang,
+  };
+}
+
+pub async fn trans(context: &Context, req: &TransReq) -> TransResult<TransRes> {
+  return with_account(context, "niutrans", context.niutrans_accounts(), |account| trans_once(context, req, account)).await;
+}
+
+async fn trans_once(context: &Context, req: &TransReq, account: usize) -> TransResult<TransRes> {
+  let client = context.client_ref("niutrans");
+  let form = [
+    ("from", niutrans_lang(req.source_ref())),
+    ("to", niutrans_lang(req.target_ref())),
+    ("apikey", context.niutrans_key(account)?),
+    ("src_text", req.src_ref())
+  ];
+  let mut request_builder = client.post(context.niutrans_address());
+  request_builder = request_builder.version(Version::HTTP_11);
+  request_builder = request_builder.form(&form);
+  let response = request_builder.send()
+    .await.map_err(send_error)?;
+  let response = read_json::<NiuTransRes>(response, |status, _| status_error(status))
+    .await?
+    .to_trans_res()?;
+  return Ok(response);
+}
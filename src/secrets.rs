@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit, Nonce};
+use chacha20poly1305::aead::Aead;
+use hmac::Hmac;
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+use crate::common::{TransError, TransResult, base64_byte, write_atomic};
+
+const PASSPHRASE_ENV: &str = "TRANSLATOR_SECRETS_PASSPHRASE";
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+// On disk the entries are a ChaCha20-Poly1305 encrypted JSON map, the key is
+// derived from a passphrase with PBKDF2-HMAC-SHA256.
+#[derive(Serialize, Deserialize)]
+struct SecretFile {
+  salt: String,
+
+  nonce: String,
+
+  data: String,
+}
+
+pub struct SecretStore {
+  path: PathBuf,
+  salt: Vec<u8>,
+  key: [u8; 32],
+  entries: BTreeMap<String, String>,
+}
+
+fn store_path() -> TransResult<PathBuf> {
+  return dirs::data_dir()
+    .map(|path| path.join("translator").join("secrets.json"))
+    .ok_or(TransError::SecretStoreError);
+}
+
+fn passphrase() -> TransResult<String> {
+  if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+    return Ok(passphrase);
+  }
+  return rpassword::prompt_password("secret store passphrase: ").map_err(|_| TransError::SecretStoreError);
+}
+
+// a new store is locked with whatever is typed, so it is asked for twice
+fn new_passphrase() -> TransResult<String> {
+  if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+    return Ok(passphrase);
+  }
+  let passphrase = rpassword::prompt_password("new secret store passphrase: ").map_err(|_| TransError::SecretStoreError)?;
+  let repeated = rpassword::prompt_password("repeat the passphrase: ").map_err(|_| TransError::SecretStoreError)?;
+  if passphrase != repeated {
+    return Err(TransError::PassphraseMismatch);
+  }
+  return Ok(passphrase);
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+  let mut key = [0u8; 32];
+  pbkdf2::pbkdf2::<Hmac<sha2::Sha256>>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+  return key;
+}
+
+fn base64_decode(data: &str) -> TransResult<Vec<u8>> {
+  return base64::decode(data).map_err(|_| TransError::SecretStoreError);
+}
+
+impl SecretStore {
+  pub fn exists() -> bool {
+    return store_path().map(|path| path.exists()).unwrap_or(false);
+  }
+
+  // opens the store, creating an empty one on first use
+  pub fn open() -> TransResult<Self> {
+    let path = store_path()?;
+    if !path.exists() {
+      let mut salt = vec![0u8; 16];
+      rand::thread_rng().fill_bytes(salt.as_mut_slice());
+      let key = derive_key(new_passphrase()?.as_str(), salt.as_slice());
+      return Ok(SecretStore { path, salt, key, entries: BTreeMap::new() });
+    }
+    let content = std::fs::read(path.as_path()).map_err(|_| TransError::SecretStoreError)?;
+    let file: SecretFile = serde_json::from_slice(content.as_slice()).map_err(|_| TransError::SecretStoreError)?;
+    let salt = base64_decode(file.salt.as_str())?;
+    let nonce = base64_decode(file.nonce.as_str())?;
+    if nonce.len() != 12 {
+      return Err(TransError::SecretStoreError);
+    }
+    let key = derive_key(passphrase()?.as_str(), salt.as_slice());
+    let data = ChaCha20Poly1305::new(&key.into())
+      .decrypt(Nonce::from_slice(nonce.as_slice()), base64_decode(file.data.as_str())?.as_slice())
+      .map_err(|_| TransError::SecretStoreError)?;
+    let entries = serde_json::from_slice(data.as_slice()).map_err(|_| TransError::SecretStoreError)?;
+    return Ok(SecretStore { path, salt, key, entries });
+  }
+
+  pub fn get(&self, name: &str) -> Option<&str> {
+    return self.entries.get(name).map(|value| value.as_str());
+  }
+
+  pub fn set(&mut self, name: &str, value: &str) {
+    self.entries.insert(String::from(name), String::from(value));
+  }
+
+  pub fn remove(&mut self, name: &str) -> bool {
+    return self.entries.remove(name).is_some();
+  }
+
+  pub fn save(&self) -> TransResult<()> {
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let data = serde_json::to_vec(&self.entries).map_err(|_| TransError::SerdeError)?;
+    let data = ChaCha20Poly1305::new(&self.key.into())
+      .encrypt(Nonce::from_slice(&nonce), data.as_slice())
+      .map_err(|_| TransError::SecretStoreError)?;
+    let file = SecretFile {
+      salt: base64_byte(self.salt.as_slice()),
+      nonce: base64_byte(&nonce),
+      data: base64_byte(data.as_slice()),
+    };
+    let content = serde_json::to_vec_pretty(&file).map_err(|_| TransError::SerdeError)?;
+    if let Some(parent) = self.path.parent() {
+      std::fs::create_dir_all(parent).map_err(|_| TransError::SecretStoreError)?;
+    }
+    #[cfg(unix)]
+    let permissions = Some(std::os::unix::fs::PermissionsExt::from_mode(0o600));
+    #[cfg(not(unix))]
+    let permissions = None;
+    // never truncated in place, a failed write keeps every stored secret
+    return write_atomic(self.path.as_path(), content.as_slice(), permissions).map_err(|_| TransError::SecretStoreError);
+  }
+}
+
+// `translator secrets set|get|rm <provider>.<field>`
+pub fn set(name: &str) -> TransResult<()> {
+  let mut store = SecretStore::open()?;
+  let value = if std::io::stdin().is_terminal() {
+    rpassword::prompt_password(format!("{}: ", name)).map_err(|_| TransError::SecretStoreError)?
+  } else {
+    let mut value = String::new();
+    std::io::stdin().read_line(&mut value).map_err(|_| TransError::SecretStoreError)?;
+    value
+  };
+  store.set(name, value.trim());
+  return store.save();
+}
+
+// reading or removing never creates the store, so there is no new passphrase to ask for
+fn open_existing() -> TransResult<SecretStore> {
+  if !SecretStore::exists() {
+    return Err(TransError::SecretStoreError);
+  }
+  return SecretStore::open();
+}
+
+pub fn get(name: &str) -> TransResult<()> {
+  let store = open_existing()?;
+  println!("{}", store.get(name).ok_or(TransError::SecretStoreError)?);
+  return Ok(());
+}
+
+pub fn remove(name: &str) -> TransResult<()> {
+  let mut store = open_existing()?;
+  if !store.remove(name) {
+    return Err(TransError::SecretStoreError);
+  }
+  return store.save();
+}
@@ -0,0 +1,354 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use crate::common::{TransError, TransResult, base64_byte, hex_byte, percent_encode, sha1_hmac, sha2_hmac, sha2_str};
+
+// The parts of an HTTP request covered by a signature. Header names are stored
+// lowercase and values trimmed, the query is encoded and sorted when rendered.
+pub struct CanonicalRequest<'a> {
+  method: &'a str,
+  path: &'a str,
+  query: Vec<(&'a str, &'a str)>,
+  headers: Vec<(String, String)>,
+  payload: &'a str,
+}
+
+impl<'a> CanonicalRequest<'a> {
+  pub fn create(method: &'a str, path: &'a str, payload: &'a str) -> Self {
+    return CanonicalRequest {
+      method,
+      path,
+      query: Vec::new(),
+      headers: Vec::new(),
+      payload,
+    };
+  }
+
+  pub fn query(mut self, name: &'a str, value: &'a str) -> Self {
+    self.query.push((name, value));
+    return self;
+  }
+
+  pub fn header(mut self, name: &str, value: &str) -> Self {
+    self.headers.push((name.to_lowercase(), String::from(value.trim())));
+    self.headers.sort_by(|a, b| a.0.cmp(&b.0));
+    return self;
+  }
+
+  pub fn header_value(&self, name: &str) -> Option<&str> {
+    return self.headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+  }
+
+  pub fn canonical_query(&self) -> String {
+    let mut query: Vec<(String, String)> = self.query.iter()
+      .map(|(name, value)| (percent_encode(name), percent_encode(value)))
+      .collect();
+    query.sort();
+    return query.iter()
+      .map(|(name, value)| format!("{}={}", name, value))
+      .collect::<Vec<String>>()
+      .join("&");
+  }
+
+  pub fn canonical_headers(&self) -> String {
+    return self.headers.iter()
+      .map(|(name, value)| format!("{}:{}\n", name, value))
+      .collect();
+  }
+
+  pub fn signed_headers(&self) -> String {
+    return self.headers.iter()
+      .map(|(name, _)| name.as_str())
+      .collect::<Vec<&str>>()
+      .join(";");
+  }
+
+  pub fn payload_hash(&self) -> String {
+    return hex_byte(sha2_str(self.payload).as_slice());
+  }
+
+  pub fn to_canonical_string(&self) -> String {
+    let sign_request = [
+      String::from(self.method),
+      String::from(self.path),
+      self.canonical_query(),
+      self.canonical_headers(),
+      self.signed_headers(),
+      self.payload_hash()
+    ];
+    return sign_request.join("\n");
+  }
+
+  pub fn header_map(&self) -> TransResult<HeaderMap> {
+    let mut headers = HeaderMap::with_capacity(self.headers.len());
+    for (name, value) in self.headers.iter() {
+      let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| TransError::HmacError)?;
+      let value = HeaderValue::from_str(value.as_str()).map_err(|_| TransError::HmacError)?;
+      headers.insert(name, value);
+    }
+    return Ok(headers);
+  }
+}
+
+// AWS SigV4 and its relatives (HuoShan) only differ in naming: the algorithm label,
+// the prefix of the secret and the scope terminator.
+pub struct V4Signer<'a> {
+  algorithm: &'a str,
+  secret_prefix: &'a str,
+  terminator: &'a str,
+  region: &'a str,
+  service: &'a str,
+}
+
+impl<'a> V4Signer<'a> {
+  pub fn aws(region: &'a str, service: &'a str) -> Self {
+    return V4Signer {
+      algorithm: "AWS4-HMAC-SHA256",
+      secret_prefix: "AWS4",
+      terminator: "aws4_request",
+      region,
+      service,
+    };
+  }
+
+  pub fn huoshan(region: &'a str, service: &'a str) -> Self {
+    return V4Signer {
+      algorithm: "HMAC-SHA256",
+      secret_prefix: "",
+      terminator: "request",
+      region,
+      service,
+    };
+  }
+
+  // value of the `x-amz-date`/`x-date` header, which must be part of the signed headers
+  pub fn timestamp(time: &DateTime<Utc>) -> String {
+    return time.format("%Y%m%dT%H%M%SZ").to_string();
+  }
+
+  pub fn sign(&self, key: &str, secret: &str, request: &CanonicalRequest, time: &DateTime<Utc>) -> TransResult<String> {
+    let date = time.format("%Y%m%d").to_string();
+    let mut hmac_data = Vec::with_capacity(4);
+    hmac_data.push(String::from(self.algorithm));
+    hmac_data.push(V4Signer::timestamp(time));
+    let credential_scope = format!("{}/{}/{}/{}", date, self.region, self.service, self.terminator);
+    hmac_data.push(credential_scope.clone());
+    hmac_data.push(hex_byte(sha2_str(request.to_canonical_string().as_str()).as_slice()));
+    let hmac_data_str = hmac_data.join("\n");
+
+    let mut hmac_key = sha2_hmac(format!("{}{}", self.secret_prefix, secret).as_bytes(), date.as_bytes())?;
+    hmac_key = sha2_hmac(hmac_key.as_slice(), self.region.as_bytes())?;
+    hmac_key = sha2_hmac(hmac_key.as_slice(), self.service.as_bytes())?;
+    hmac_key = sha2_hmac(hmac_key.as_slice(), self.terminator.as_bytes())?;
+    let signature = sha2_hmac(hmac_key.as_slice(), hmac_data_str.as_bytes())?;
+
+    return Ok(format!("{} Credential={}/{}, SignedHeaders={}, Signature={}",
+                      self.algorithm, key, credential_scope, request.signed_headers(), hex_byte(signature.as_slice())));
+  }
+}
+
+// Tencent Cloud TC3-HMAC-SHA256
+pub struct Tc3Signer<'a> {
+  service: &'a str,
+}
+
+impl<'a> Tc3Signer<'a> {
+  pub fn create(service: &'a str) -> Self {
+    return Tc3Signer {
+      service
+    };
+  }
+
+  // value of the `x-tc-timestamp` header
+  pub fn timestamp(time: &DateTime<Utc>) -> String {
+    return time.timestamp().to_string();
+  }
+
+  pub fn sign(&self, key: &str, secret: &str, request: &CanonicalRequest, time: &DateTime<Utc>) -> TransResult<String> {
+    let date = time.format("%Y-%m-%d").to_string();
+    let mut hmac_data = Vec::with_capacity(4);
+    hmac_data.push(String::from("TC3-HMAC-SHA256"));
+    hmac_data.push(Tc3Signer::timestamp(time));
+    let credential_scope = format!("{}/{}/tc3_request", date, self.service);
+    hmac_data.push(credential_scope.clone());
+    hmac_data.push(hex_byte(sha2_str(request.to_canonical_string().as_str()).as_slice()));
+    let hmac_data_str = hmac_data.join("\n");
+
+    let mut hmac_key = sha2_hmac(format!("TC3{}", secret).as_bytes(), date.as_bytes())?;
+    hmac_key = sha2_hmac(hmac_key.as_slice(), self.service.as_bytes())?;
+    hmac_key = sha2_hmac(hmac_key.as_slice(), "tc3_request".as_bytes())?;
+    let signature = sha2_hmac(hmac_key.as_slice(), hmac_data_str.as_bytes())?;
+
+    return Ok(format!("TC3-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                      key, credential_scope, request.signed_headers(), hex_byte(signature.as_slice())));
+  }
+}
+
+// Alibaba Cloud ROA HMAC-SHA1: the standard headers by position, then every
+// `x-acs-*` header, then the resource.
+pub struct AcsSigner;
+
+impl AcsSigner {
+  // value of the `date` header
+  pub fn timestamp(time: &DateTime<Utc>) -> String {
+    return time.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+  }
+
+  pub fn sign(key: &str, secret: &str, request: &CanonicalRequest) -> TransResult<String> {
+    let mut sign_request = Vec::with_capacity(8);
+    sign_request.push(String::from(request.method));
+    sign_request.push(String::from(request.header_value("accept").unwrap_or_default()));
+    sign_request.push(String::from(request.header_value("content-md5").unwrap_or_default()));
+    sign_request.push(String::from(request.header_value("content-type").unwrap_or_default()));
+    sign_request.push(String::from(request.header_value("date").unwrap_or_default()));
+    for (name, value) in request.headers.iter().filter(|(name, _)| name.starts_with("x-acs-")) {
+      sign_request.push(format!("{}:{}", name, value));
+    }
+    let query = request.canonical_query();
+    if query.is_empty() {
+      sign_request.push(String::from(request.path));
+    } else {
+      sign_request.push(format!("{}?{}", request.path, query));
+    }
+    let sign_request_str = sign_request.join("\n");
+
+    let signature = base64_byte(sha1_hmac(secret.as_bytes(), sign_request_str.as_bytes())?.as_slice());
+    return Ok(format!("acs {}:{}", key, signature));
+  }
+}
+
+// Alibaba Cloud V3 (ACS3-HMAC-SHA256), the signed headers must contain `host`
+// and every `x-acs-*` header including `x-acs-content-sha256`.
+pub struct Acs3Signer;
+
+impl Acs3Signer {
+  // value of the `x-acs-date` header
+  pub fn timestamp(time: &DateTime<Utc>) -> String {
+    return time.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+  }
+
+  pub fn sign(key: &str, secret: &str, request: &CanonicalRequest) -> TransResult<String> {
+    let request_hash = hex_byte(sha2_str(request.to_canonical_string().as_str()).as_slice());
+    let sign_request_str = format!("ACS3-HMAC-SHA256\n{}", request_hash);
+    let signature = sha2_hmac(secret.as_bytes(), sign_request_str.as_bytes())?;
+    return Ok(format!("ACS3-HMAC-SHA256 Credential={},SignedHeaders={},Signature={}",
+                      key, request.signed_headers(), hex_byte(signature.as_slice())));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+  use super::*;
+
+  // https://github.com/awslabs/aws-c-auth/tree/main/tests/aws-signing-test-suite/v4
+  const AWS_KEY: &str = "AKIDEXAMPLE";
+  const AWS_SECRET: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+
+  fn aws_time() -> DateTime<Utc> {
+    return Utc.timestamp_opt(1440938160, 0).unwrap();
+  }
+
+  #[test]
+  fn aws_get_vanilla() {
+    let request = CanonicalRequest::create("GET", "/", "")
+      .header("Host", "example.amazonaws.com")
+      .header("X-Amz-Date", V4Signer::timestamp(&aws_time()).as_str());
+    let authorization = V4Signer::aws("us-east-1", "service")
+      .sign(AWS_KEY, AWS_SECRET, &request, &aws_time()).unwrap();
+    assert_eq!(authorization, "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+      SignedHeaders=host;x-amz-date, Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31");
+  }
+
+  #[test]
+  fn aws_post_vanilla() {
+    let request = CanonicalRequest::create("POST", "/", "")
+      .header("Host", "example.amazonaws.com")
+      .header("X-Amz-Date", V4Signer::timestamp(&aws_time()).as_str());
+    let authorization = V4Signer::aws("us-east-1", "service")
+      .sign(AWS_KEY, AWS_SECRET, &request, &aws_time()).unwrap();
+    assert!(authorization.ends_with("Signature=5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b"));
+  }
+
+  // https://docs.aws.amazon.com/general/latest/gr/sigv4-signed-request-examples.html
+  #[test]
+  fn aws_iam_list_users() {
+    let request = CanonicalRequest::create("GET", "/", "")
+      .query("Version", "2010-05-08")
+      .query("Action", "ListUsers")
+      .header("Content-Type", "application/x-www-form-urlencoded; charset=utf-8")
+      .header("Host", "iam.amazonaws.com")
+      .header("X-Amz-Date", V4Signer::timestamp(&aws_time()).as_str());
+    assert_eq!(request.canonical_query(), "Action=ListUsers&Version=2010-05-08");
+    let authorization = V4Signer::aws("us-east-1", "iam")
+      .sign(AWS_KEY, AWS_SECRET, &request, &aws_time()).unwrap();
+    assert!(authorization.ends_with("Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"));
+  }
+
+  // computed with the signing code of the original `huoshan.rs`, which was run with
+  // its clock fixed at `aws_time()`
+  #[test]
+  fn huoshan_translate_text() {
+    let request = CanonicalRequest::create("POST", "/", r#"{"TargetLanguage":"zh","TextList":["hello"]}"#)
+      .query("Action", "TranslateText")
+      .query("Version", "2020-06-01")
+      .header("content-type", "application/json; charset=utf-8")
+      .header("host", "open.volcengineapi.com")
+      .header("x-date", V4Signer::timestamp(&aws_time()).as_str());
+    let authorization = V4Signer::huoshan("cn-north-1", "translate")
+      .sign("huoshan-key", "huoshan-secret", &request, &aws_time()).unwrap();
+    assert_eq!(authorization, "HMAC-SHA256 Credential=huoshan-key/20150830/cn-north-1/translate/request, \
+      SignedHeaders=content-type;host;x-date, Signature=6e2d9f9fc8193c826d6eb151028601c99a5745804b240b1985f87df3ce09b869");
+  }
+
+  // computed with the signing code of the original `aliyun.rs`, which was run with
+  // its clock fixed at `aws_time()` and dated the request in RFC 2822
+  #[test]
+  fn aliyun_roa_translate() {
+    let body = r#"{"FormatType":"text","SourceLanguage":"en","TargetLanguage":"zh","SourceText":"hello","Scene":"general"}"#;
+    let body_hash = base64_byte(crate::common::md5_str(body).as_slice());
+    assert_eq!(body_hash, "KDJbdT1aUF7xC7n2VQ9ayA==");
+    let request = CanonicalRequest::create("POST", "/api/translate/web/general", body)
+      .header("accept", "application/json")
+      .header("content-md5", body_hash.as_str())
+      .header("content-type", "application/json; charset=utf-8")
+      .header("date", "Sun, 30 Aug 2015 12:36:00 +0000")
+      .header("host", "mt.cn-hangzhou.aliyuncs.com")
+      .header("x-acs-signature-method", "HMAC-SHA1")
+      .header("x-acs-signature-nonce", "1440938160");
+    let authorization = AcsSigner::sign("aliyun-key", "aliyun-secret", &request).unwrap();
+    assert_eq!(authorization, "acs aliyun-key:oKclqoxWzRdIuaqUHVmCuLT10DU=");
+  }
+
+  // https://cloud.tencent.com/document/api/213/30654
+  #[test]
+  fn tencent_describe_instances() {
+    let time = Utc.timestamp_opt(1551113065, 0).unwrap();
+    let body = r#"{"Limit": 1, "Filters": [{"Values": ["\u672a\u547d\u540d"], "Name": "instance-name"}]}"#;
+    let request = CanonicalRequest::create("POST", "/", body)
+      .header("Content-Type", "application/json; charset=utf-8")
+      .header("Host", "cvm.tencentcloudapi.com");
+    let authorization = Tc3Signer::create("cvm")
+      .sign("AKIDz8krbsJ5yKBZQpn74WFkmLPx3*******", "Gu5t9xGARNpq86cd98joQYCN3*******", &request, &time).unwrap();
+    assert_eq!(authorization, "TC3-HMAC-SHA256 Credential=AKIDz8krbsJ5yKBZQpn74WFkmLPx3*******/2019-02-25/cvm/tc3_request, \
+      SignedHeaders=content-type;host, Signature=2230eefd229f582d8b1b891af7107b91597240707d778ab3738f756258d7652c");
+  }
+
+  // https://help.aliyun.com/zh/sdk/product-overview/v3-request-structure-and-signature
+  #[test]
+  fn aliyun_run_instances() {
+    let request = CanonicalRequest::create("POST", "/", "")
+      .query("ImageId", "win2019_1809_x64_dtc_zh-cn_40G_alibase_20230811.vhd")
+      .query("RegionId", "cn-shanghai")
+      .header("host", "ecs.cn-shanghai.aliyuncs.com")
+      .header("x-acs-action", "RunInstances")
+      .header("x-acs-content-sha256", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+      .header("x-acs-date", "2023-10-26T10:22:32Z")
+      .header("x-acs-signature-nonce", "3156853299f313e23d1673dc12e1703d")
+      .header("x-acs-version", "2014-05-26");
+    assert_eq!(request.payload_hash(), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    let authorization = Acs3Signer::sign("YourAccessKeyId", "YourAccessKeySecret", &request).unwrap();
+    assert_eq!(authorization, "ACS3-HMAC-SHA256 Credential=YourAccessKeyId,\
+      SignedHeaders=host;x-acs-action;x-acs-content-sha256;x-acs-date;x-acs-signature-nonce;x-acs-version,\
+      Signature=06563a9e1b43f5dfe96b81484da74bceab24a1d853912eee15083a6f0f3283c0");
+  }
+}
@@ -1,9 +1,12 @@
-use chrono::Utc;
 use serde::{Serialize, Deserialize};
-use reqwest::Version;
-use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue, HOST};
-use crate::{Context, TransError, TransRes, TransResult};
-use crate::common::{hex_byte, sha2_hmac, sha2_str};
+use serde::de::DeserializeOwned;
+use reqwest::{StatusCode, Version};
+use reqwest::header::{AUTHORIZATION, HeaderName, HeaderValue};
+use crate::{Context, TransError, TransReq, TransRes, TransResult};
+use crate::common::{classify_error, json_text, read_json, retry_on_skew, send_error, server_date, with_account};
+use crate::sign::{CanonicalRequest, Tc3Signer};
+
+const TENCENT_BATCH_LENGTH: usize = 6000;
 
 #[derive(Serialize, Debug)]
 pub struct TencentReq {
@@ -11,7 +14,7 @@ pub struct TencentReq {
   src: String,
 
   #[serde(rename(serialize = "ProjectId"))]
-  project_id: i32,
+  project_id: i64,
 
   #[serde(rename(serialize = "Target"))]
   target: String,
@@ -21,16 +24,60 @@ pub struct TencentReq {
 }
 
 impl TencentReq {
-  fn create(src: &str, source: &str, target: &str) -> Self {
+  fn create(src: &str, source: &str, target: &str, project_id: i64) -> Self {
     return TencentReq {
       src: String::from(src),
-      project_id: 0,
+      project_id,
+      target: String::from(target),
+      source: String::from(source)
+    };
+  }
+}
+
+#[derive(Serialize, Debug)]
+struct TencentBatchReq {
+  #[serde(rename(serialize = "SourceTextList"))]
+  src: Vec<String>,
+
+  #[serde(rename(serialize = "ProjectId"))]
+  project_id: i64,
+
+  #[serde(rename(serialize = "Target"))]
+  target: String,
+
+  #[serde(rename(serialize = "Source"))]
+  source: String
+}
+
+impl TencentBatchReq {
+  fn create(src: &[String], source: &str, target: &str, project_id: i64) -> Self {
+    return TencentBatchReq {
+      src: src.to_vec(),
+      project_id,
       target: String::from(target),
       source: String::from(source)
     };
   }
 }
 
+#[derive(Deserialize, Debug)]
+struct TencentBatchRes {
+  #[serde(rename(deserialize = "Response"))]
+  data: TencentInnerBatchRes
+}
+
+impl TencentBatchRes {
+  fn to_trans_list(&self) -> Vec<TransRes> {
+    return self.data.dst.iter().map(|dst| TransRes::create(dst.clone())).collect();
+  }
+}
+
+#[derive(Deserialize, Debug)]
+struct TencentInnerBatchRes {
+  #[serde(rename(deserialize = "TargetTextList"))]
+  dst: Vec<String>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct TencentRes {
   #[serde(rename(deserialize = "Response"))]
@@ -59,56 +106,74 @@ struct TencentInnerRes {
 
 }
 
-pub async fn trans(context: &Context) -> TransResult<TransRes> {
+// `{"Response": {"Error": {"Code": "AuthFailure.SignatureExpire", "Message": ".."}}}`
+fn error(status: StatusCode, body: &serde_json::Value) -> TransError {
+  let error = &body["Response"]["Error"];
+  return classify_error(status, json_text(&error["Code"]).as_str(), json_text(&error["Message"]).as_str());
+}
+
+pub async fn trans(context: &Context, req: &TransReq) -> TransResult<TransRes> {
+  return with_account(context, "tencent", context.tencent_accounts(), |account| {
+    retry_on_skew(context, move || async move {
+      let body = TencentReq::create(req.src_ref(), req.source_ref(), req.target_ref(), context.tencent_project_id());
+      let response = call::<_, TencentRes>(context, account, "TextTranslate", &body).await?;
+      return Ok(response.to_trans_res());
+    })
+  }).await;
+}
+
+// `TextTranslateBatch`, the whole request is limited to 6000 characters
+pub async fn trans_batch(context: &Context, segments: &[String]) -> TransResult<Vec<TransRes>> {
   let req = context.req_ref();
-  let client = context.client_ref();
-  let current_time = Utc::now();
-  let current_data = current_time.format("%Y-%m-%d").to_string();
-  let current_instant = current_time.timestamp().to_string();
-  let body = TencentReq::create(req.src_ref(), req.source_ref(), req.target_ref());
-  let body_str = serde_json::to_string(&body).map_err(|_| TransError::SerdeError)?;
-
-  let mut sign_request = Vec::with_capacity(6);
-  sign_request.push("POST");
-  sign_request.push("/");
-  sign_request.push("");
-  sign_request.push("content-type:application/json; charset=utf-8\nhost:tmt.tencentcloudapi.com\n");
-  sign_request.push("content-type;host");
-  let body_hash = hex_byte(sha2_str(body_str.as_ref()).as_slice());
-  sign_request.push(body_hash.as_ref());
-  let sign_request_str = sign_request.join("\n");
-
-  let mut hmac_data = Vec::with_capacity(4);
-  hmac_data.push("TC3-HMAC-SHA256");
-  hmac_data.push(current_instant.as_ref());
-  let credential_scope = format!("{}/tmt/tc3_request", current_data.as_str());
-  hmac_data.push(credential_scope.as_ref());
-  let request_hash = hex_byte(sha2_str(sign_request_str.as_str()).as_slice());
-  hmac_data.push(request_hash.as_ref());
-  let hmac_data_str = hmac_data.join("\n");
-
-  let tencent_secret = context.tencent_secret()?;
-  let mut hmac_key = sha2_hmac(format!("TC3{}", tencent_secret).as_bytes(), current_data.as_bytes())?;
-  hmac_key = sha2_hmac(hmac_key.as_slice(), "tmt".as_bytes())?;
-  hmac_key = sha2_hmac(hmac_key.as_slice(), "tc3_request".as_bytes())?;
-  let signature = sha2_hmac(hmac_key.as_slice(), hmac_data_str.as_bytes())?;
-
-  let mut authorization = String::from("TC3-HMAC-SHA256 Credential=");
-  authorization.push_str(context.tencent_key()?);
-  authorization.push_str("/");
-  authorization.push_str(current_data.as_ref());
-  authorization.push_str("/tmt/tc3_request");
-  authorization.push_str(", SignedHeaders=content-type;host");
-  authorization.push_str(", Signature=");
-  authorization.push_str(hex_byte(signature.as_slice()).as_ref());
-
-  let mut headers = HeaderMap::with_capacity(7);
-  headers.insert(HeaderName::from_static("x-tc-action"), HeaderValue::from_str("TextTranslate").unwrap());
+  let mut results = Vec::with_capacity(segments.len());
+  let mut start = 0;
+  while start < segments.len() {
+    let mut end = start + 1;
+    let mut length = segments[start].chars().count();
+    while end < segments.len() && length + segments[end].chars().count() <= TENCENT_BATCH_LENGTH {
+      length += segments[end].chars().count();
+      end += 1;
+    }
+    let texts = &segments[start..end];
+    let list = with_account(context, "tencent", context.tencent_accounts(), |account| {
+      retry_on_skew(context, move || async move {
+        let body = TencentBatchReq::create(texts, req.source_ref(), req.target_ref(), context.tencent_project_id());
+        let response = call::<_, TencentBatchRes>(context, account, "TextTranslateBatch", &body).await?;
+        return Ok(response.to_trans_list());
+      })
+    }).await?;
+    if list.len() != texts.len() {
+      return Err(TransError::ResponseError);
+    }
+    results.extend(list);
+    start = end;
+  }
+  return Ok(results);
+}
+
+async fn call<B, R>(context: &Context, account: usize, action: &str, body: &B) -> TransResult<R>
+  where
+    B: Serialize,
+    R: DeserializeOwned,
+{
+  let client = context.client_ref("tencent");
+  let current_time = context.now();
+  let body_str = serde_json::to_string(body).map_err(|_| TransError::SerdeError)?;
+
+  let sign_request = CanonicalRequest::create("POST", "/", body_str.as_str())
+    .header("content-type", "application/json; charset=utf-8")
+    .header("host", "tmt.tencentcloudapi.com");
+  let authorization = Tc3Signer::create("tmt")
+    .sign(context.tencent_key(account)?, context.tencent_secret(account)?, &sign_request, &current_time)?;
+
+  let mut headers = sign_request.header_map()?;
+  headers.insert(HeaderName::from_static("x-tc-action"), HeaderValue::from_str(action).unwrap());
   headers.insert(HeaderName::from_static("x-tc-version"), HeaderValue::from_str("2018-03-21").unwrap());
   headers.insert(HeaderName::from_static("x-tc-region"), HeaderValue::from_str("ap-shanghai").unwrap());
-  headers.insert(HeaderName::from_static("x-tc-timestamp"), HeaderValue::from_str(current_instant.as_ref()).unwrap());
-  headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json; charset=utf-8").unwrap());
-  headers.insert(HOST, HeaderValue::from_str("tmt.tencentcloudapi.com").unwrap());
+  headers.insert(HeaderName::from_static("x-tc-timestamp"), HeaderValue::from_str(Tc3Signer::timestamp(&current_time).as_str()).unwrap());
+  if let Some(token) = context.tencent_token(account) {
+    headers.insert(HeaderName::from_static("x-tc-token"), HeaderValue::from_str(token).map_err(|_| TransError::TencentConfig)?);
+  }
   headers.insert(AUTHORIZATION, HeaderValue::from_str(authorization.as_ref()).unwrap());
 
   let mut request_builder = client.post(context.tencent_address());
@@ -116,9 +181,8 @@ pub async fn trans(context: &Context) -> TransResult<TransRes> {
   request_builder = request_builder.headers(headers);
   request_builder = request_builder.body(body_str);
   let response = request_builder.send()
-    .await.map_err(|_| TransError::RequestError)?
-    .json::<TencentRes>()
-    .await.map_err(|_| TransError::ResponseError)?
-    .to_trans_res();
-  return Ok(response);
-}
\ No newline at end of file
+    .await.map_err(send_error)?;
+  let date = server_date(&response);
+  return read_json::<R>(response, error)
+    .await.map_err(|error| context.correct_clock(error, date));
+}
@@ -0,0 +1,170 @@
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use crate::common::{Config, Context, TransError, TransReq, TransRes, TransResult, write_atomic};
+use crate::trans_engine;
+
+const VERIFY_TIMEOUT: u64 = 10;
+
+struct Provider {
+  name: &'static str,
+  title: &'static str,
+  console: &'static str,
+  // (field, prompt), every value is read without echo
+  fields: &'static [(&'static str, &'static str)],
+}
+
+const PROVIDERS: [Provider; 8] = [
+  Provider { name: "baidu", title: "百度", console: "https://fanyi-api.baidu.com/api/trans/product/desktop", fields: &[("key", "APP ID"), ("secret", "APP SECRET")] },
+  Provider { name: "huoshan", title: "火山", console: "https://console.volcengine.com/translate", fields: &[("key", "Access Key ID"), ("secret", "Secret Access Key")] },
+  Provider { name: "aliyun", title: "阿里", console: "https://mt.console.aliyun.com/basic", fields: &[("key", "AccessKey ID"), ("secret", "AccessKey Secret")] },
+  Provider { name: "tencent", title: "腾讯", console: "https://console.cloud.tencent.com/tmt", fields: &[("key", "SecretId"), ("secret", "SecretKey")] },
+  Provider { name: "youdao", title: "有道", console: "https://ai.youdao.com/product-fanyi-text.s", fields: &[("key", "应用ID"), ("secret", "应用密钥")] },
+  Provider { name: "caiyun", title: "彩云小译", console: "https://dashboard.caiyunapp.com", fields: &[("token", "Token")] },
+  Provider { name: "niutrans", title: "小牛翻译", console: "https://niutrans.com/cloud/api/list", fields: &[("key", "API-KEY")] },
+  Provider { name: "aws", title: "AWS", console: "https://aws.amazon.com/translate/", fields: &[("key", "Access key ID"), ("secret", "Secret access key")] },
+];
+
+const HEADER: &str = "\
+# Generated by `translator config init`, see README.md for every option.
+#
+# Top level defaults, command line flags win over them:
+# source = \"auto\"
+# target = \"zh\"
+# engines = [\"baidu\", \"tencent\"]
+# format = \"text\"   # `text` or `json`
+# timeout = 10      # seconds
+# color = \"auto\"    # `auto`, `always` or `never`
+#
+# Instead of plain text values, `secret_command = \"pass show baidu\"` or
+# `translator secrets set baidu.secret` keep credentials out of this file.
+";
+
+fn read_line(prompt: &str) -> TransResult<String> {
+  print!("{}", prompt);
+  std::io::stdout().flush().map_err(|_| TransError::InitError)?;
+  let mut line = String::new();
+  if std::io::stdin().read_line(&mut line).map_err(|_| TransError::InitError)? == 0 {
+    return Err(TransError::InitError);
+  }
+  return Ok(String::from(line.trim()));
+}
+
+fn read_hidden(prompt: &str) -> TransResult<String> {
+  if !std::io::stdin().is_terminal() {
+    return read_line(prompt);
+  }
+  let value = rpassword::prompt_password(prompt).map_err(|_| TransError::InitError)?;
+  return Ok(String::from(value.trim()));
+}
+
+fn confirm(prompt: &str, default: bool) -> TransResult<bool> {
+  let hint = if default { "[Y/n]" } else { "[y/N]" };
+  let answer = read_line(format!("{} {} ", prompt, hint).as_str())?;
+  return Ok(match answer.to_lowercase().as_str() {
+    "y" | "yes" => true,
+    "n" | "no" => false,
+    _ => default,
+  });
+}
+
+fn toml_string(value: &str) -> String {
+  return toml::Value::String(String::from(value)).to_string();
+}
+
+// `--config` if given, else the user config
+fn target_path(explicit: Option<&Path>) -> TransResult<PathBuf> {
+  if let Some(path) = explicit {
+    return Ok(PathBuf::from(path));
+  }
+  return dirs::config_dir()
+    .map(|dir| dir.join("translator").join("translator.toml"))
+    .ok_or(TransError::InitError);
+}
+
+// translates a single word with nothing but the given section configured
+async fn verify(provider: &str, section: &toml::value::Table) -> TransResult<TransRes> {
+  let mut value = toml::value::Table::new();
+  value.insert(String::from(provider), toml::Value::Table(section.clone()));
+  let config: Config = toml::Value::Table(value).try_into().map_err(|_| TransError::ParseConfig)?;
+  let context = Context::configure(TransReq::create("hello", "en", "zh"), config, Some(VERIFY_TIMEOUT))?;
+  return trans_engine(provider, &context, context.req_ref()).await;
+}
+
+async fn ask_provider(provider: &Provider) -> TransResult<Option<toml::value::Table>> {
+  loop {
+    let mut section = toml::value::Table::new();
+    for (field, prompt) in provider.fields {
+      let value = read_hidden(format!("  {}: ", prompt).as_str())?;
+      section.insert(String::from(*field), toml::Value::String(value));
+    }
+    if provider.name == "aws" {
+      let region = read_line("  region [us-east-1]: ")?;
+      if !region.is_empty() {
+        section.insert(String::from("region"), toml::Value::String(region));
+      }
+    }
+    print!("  verifying... ");
+    let _ = std::io::stdout().flush();
+    match verify(provider.name, &section).await {
+      Ok(res) => {
+        println!("{} hello -> {}", console::style("ok").green(), res.result());
+        return Ok(Some(section));
+      }
+      Err(error) => println!("{} {:?}", console::style("failed").red(), error),
+    }
+    if confirm("  enter the credentials again?", true)? {
+      continue;
+    }
+    let keep = confirm("  keep them anyway?", false)?;
+    return Ok(if keep { Some(section) } else { None });
+  }
+}
+
+fn render(sections: &[(&Provider, toml::value::Table)]) -> String {
+  let mut content = String::from(HEADER);
+  for (provider, section) in sections {
+    content.push_str(format!("\n# {}: {}\n[{}]\n", provider.title, provider.console, provider.name).as_str());
+    for (field, value) in section {
+      let value = value.as_str().unwrap_or_default();
+      content.push_str(format!("{} = {}\n", field, toml_string(value)).as_str());
+    }
+  }
+  return content;
+}
+
+fn write_config(path: &Path, content: &str) -> TransResult<()> {
+  if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+    std::fs::create_dir_all(parent).map_err(|_| TransError::InitError)?;
+  }
+  #[cfg(unix)]
+  let permissions = Some(std::os::unix::fs::PermissionsExt::from_mode(0o600));
+  #[cfg(not(unix))]
+  let permissions = None;
+  // an interrupted write leaves the previous config as it was
+  return write_atomic(path, content.as_bytes(), permissions).map_err(|_| TransError::InitError);
+}
+
+// `translator config init`
+pub async fn init(explicit: Option<&Path>) -> TransResult<()> {
+  let path = target_path(explicit)?;
+  println!("writing {}", path.display());
+  if path.exists() && !confirm("the file exists, overwrite it?", false)? {
+    return Ok(());
+  }
+  let mut sections = Vec::with_capacity(PROVIDERS.len());
+  for provider in PROVIDERS.iter() {
+    if !confirm(format!("enable {} ({})?", provider.name, provider.title).as_str(), false)? {
+      continue;
+    }
+    if let Some(section) = ask_provider(provider).await? {
+      sections.push((provider, section));
+    }
+  }
+  if sections.is_empty() {
+    println!("no provider enabled, nothing written");
+    return Ok(());
+  }
+  write_config(path.as_path(), render(sections.as_slice()).as_str())?;
+  println!("{} {}", console::style("written").green(), path.display());
+  return Ok(());
+}
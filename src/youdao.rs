@@ -0,0 +1,138 @@
+use reqwest::Version;
+use serde::Deserialize;
+use crate::common::{Context, TransReq, TransRes, TransError, TransResult, hex_byte, sha2_str, read_json, send_error, status_error, with_account};
+
+#[derive(Deserialize, Debug)]
+pub struct YoudaoRes {
+  #[serde(rename(deserialize = "errorCode"))]
+  code: String,
+
+  #[serde(default)]
+  translation: Vec<String>,
+
+  basic: Option<YoudaoBasicRes>,
+
+  #[serde(default)]
+  web: Vec<YoudaoWebRes>,
+}
+
+impl YoudaoRes {
+  fn to_trans_res(&self) -> TransResult<TransRes> {
+    if self.code != "0" {
+      return Err(youdao_error(self.code.as_str()));
+    }
+    let result = self.translation.first().ok_or(TransError::ResponseError)?;
+    let mut res = TransRes::create(result.clone());
+    if let Some(basic) = self.basic.as_ref() {
+      if let Some(phonetic) = basic.phonetic() {
+        res = res.with_phonetic(phonetic);
+      }
+      res = res.with_explains(basic.explains.clone());
+    }
+    let web = self.web.iter()
+      .map(|web| format!("{}: {}", web.key, web.value.join("; ")))
+      .collect();
+    return Ok(res.with_explains(web));
+  }
+}
+
+// https://ai.youdao.com/DOCSIRMA/html/trans/api/wbfy/index.html error codes
+fn youdao_error(code: &str) -> TransError {
+  return match code {
+    "108" | "110" | "111" | "202" => TransError::AuthError,
+    "206" => TransError::ClockSkew,
+    "401" => TransError::QuotaExceeded,
+    "411" | "412" => TransError::RateLimited,
+    _ => TransError::ResponseError,
+  };
+}
+
+#[derive(Deserialize, Debug)]
+struct YoudaoBasicRes {
+  phonetic: Option<String>,
+
+  #[serde(rename(deserialize = "us-phonetic"))]
+  us_phonetic: Option<String>,
+
+  #[serde(rename(deserialize = "uk-phonetic"))]
+  uk_phonetic: Option<String>,
+
+  #[serde(default)]
+  explains: Vec<String>,
+}
+
+impl YoudaoBasicRes {
+  fn phonetic(&self) -> Option<String> {
+    return match (self.uk_phonetic.as_ref(), self.us_phonetic.as_ref()) {
+      (Some(uk), Some(us)) if uk != us => Some(format!("英 [{}] 美 [{}]", uk, us)),
+      _ => self.phonetic.as_ref().or(self.us_phonetic.as_ref()).map(|phonetic| format!("[{}]", phonetic)),
+    };
+  }
+}
+
+#[derive(Deserialize, Debug)]
+struct YoudaoWebRes {
+  key: String,
+
+  #[serde(default)]
+  value: Vec<String>,
+}
+
+fn youdao_lang(lang: &str) -> &str {
+  return match lang {
+    "zh" | "zh-CN" | "zh-Hans" => "zh-CHS",
+    "cht" | "zh-TW" | "zh-Hant" => "zh-CHT",
+    _ => lang,
+  };
+}
+
+// the v3 signature only covers the first and last ten characters of long input.
+fn youdao_truncate(src: &str) -> String {
+  let chars: Vec<char> = src.chars().collect();
+  if chars.len() <= 20 {
+    return String::from(src);
+  }
+  let head: String = chars[..10].iter().collect();
+  let tail: String = chars[chars.len() - 10..].iter().collect();
+  return format!("{}{}{}", head, chars.len(), tail);
+}
+
+pub async fn trans(context: &Context, req: &TransReq) -> TransResult<TransRes> {
+  return with_account(context, "youdao", context.youdao_accounts(), |account| trans_once(context, req, account)).await;
+}
+
+async fn trans_once(context: &Context, req: &TransReq, account: usize) -> TransResult<TransRes> {
+  let client = context.client_ref("youdao");
+  let current_time = context.now();
+  let salt = context.nonce();
+  let curtime = current_time.timestamp().to_string();
+
+  let mut sign_content = String::from(context.youdao_key(account)?);
+  sign_content.push_str(youdao_truncate(req.src_ref()).as_str());
+  sign_content.push_str(salt.as_str());
+  sign_content.push_str(curtime.as_str());
+  sign_content.push_str(context.youdao_secret(account)?);
+  let sign = hex_byte(sha2_str(sign_content.as_str()).as_slice());
+  let mut form = vec![
+    ("q", req.src_ref()),
+    ("from", youdao_lang(req.source_ref())),
+    ("to", youdao_lang(req.target_ref())),
+    ("appKey", context.youdao_key(account)?),
+    ("salt", salt.as_str()),
+    ("sign", sign.as_str()),
+    ("signType", "v3"),
+    ("curtime", curtime.as_str())
+  ];
+  if let Some(domain) = context.youdao_domain() {
+    form.push(("domain", domain));
+  }
+  let mut request_builder = client.post(context.youdao_address());
+  request_builder = request_builder.version(Version::HTTP_11);
+  request_builder = request_builder.form(&form);
+  let response = request_builder.send()
+    .await.map_err(send_error)?;
+  let response = read_json::<YoudaoRes>(response, |status, _| status_error(status))
+    .await?
+    .to_trans_res()?;
+  return Ok(response);
+}